@@ -1,13 +1,39 @@
+// The IDL account instructions that `#[program]` generates at crate level still
+// call the deprecated `AccountInfo::realloc`, and Anchor's macros gate code on
+// `anchor-debug`/`custom-*` features and `target_os = "solana"`, none of which
+// this manifest declares. Neither can be allowed on the generated items alone.
+#![allow(deprecated, unexpected_cfgs)]
+
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::system_instruction;
-use anchor_lang::solana_program::program::{invoke, invoke_signed};
-use anchor_spl::token::{self, Token, TokenAccount};
+use anchor_lang::system_program;
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022_extensions;
+use anchor_spl::token_interface::{self, spl_token_2022, Mint, TokenAccount, TokenInterface};
+use spl_token_2022::extension::transfer_fee::TransferFeeAmount;
+use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
 
 declare_id!("Fm9JpyaGRGJSqMFHr2d4Yg5pMFg9x1cQPGaV9GeGS1Nq");
 
-// Deadline range constants (in seconds)
-const MIN_DEADLINE: i64 = 60;           // 1 minute
-const MAX_DEADLINE: i64 = 8 * 30 * 24 * 60 * 60; // 8 months (approximate)
+// Denominator for basis-point splits (10000 bps = 100%)
+const BPS_DENOMINATOR: u64 = 10_000;
+
+// Maximum number of payment milestones per order
+const MAX_MILESTONES: usize = 5;
+
+// Maximum number of deadline/amount proposals per order, including the initial terms
+const MAX_NEGOTIATION_ROUNDS: usize = 6;
+
+// Maximum number of deadline extensions that can be granted on one order
+const MAX_EXTENSIONS: usize = 5;
+
+// Maximum number of verifiers on an order's panel
+const MAX_VERIFIERS: usize = 5;
+
+// Maximum number of jurisdictions on a registered verifier's profile
+const MAX_JURISDICTIONS: usize = 8;
+
+// Length of a late-penalty day, in seconds
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
 
 // Order history entry
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
@@ -17,6 +43,92 @@ pub struct OrderHistoryEntry {
     pub description: String, // Max 100 characters
 }
 
+// Program-wide settings, set by the config admin
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct ConfigParams {
+    pub treasury: Pubkey,
+    pub min_deadline: i64, // Seconds
+    pub max_deadline: i64, // Seconds
+    pub fee_bps: u16,
+    pub min_fee: u64, // Flat minimum fee on SOL orders, in lamports; SPL mints set their own
+    pub max_clock_skew: i64, // Seconds, 0 = only the cluster clock is accepted
+    pub paused: bool,
+    pub require_registered_verifier: bool, // Orders must name an active, staked registry verifier
+    pub min_verifier_stake: u64, // Lamports a registered verifier needs staked to be chosen
+    pub verifier_unbonding_period: i64, // Seconds, stake stays slashable after an unbond request
+}
+
+// Public profile of a registered verifier
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct VerifierProfile {
+    pub name_hash: [u8; 32], // Hash of the legal name / credentials document
+    pub jurisdictions: Vec<[u8; 2]>, // ISO 3166-1 alpha-2 codes, max MAX_JURISDICTIONS
+    pub fee_bps: u16, // Inspection fee schedule: share of the order amount
+    pub flat_fee: u64, // plus a flat fee, in lamports
+    pub active: bool, // Whether the verifier is taking new orders
+}
+
+// Payment tranche on a milestone schedule
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct Milestone {
+    pub label: String, // Max 32 characters
+    pub amount: u64,
+    pub deadline: i64, // Refundable to importer once passed without approval
+    pub approver: Pubkey, // Signs off the milestone once submitted
+    pub state: MilestoneState,
+}
+
+// Milestone definition supplied at order creation
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct MilestoneParams {
+    pub label: String, // Max 32 characters
+    pub amount: u64,
+    pub deadline: i64,
+    pub approver: Pubkey,
+}
+
+// One round of deadline/amount negotiation
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct DeadlineProposal {
+    pub proposer: Pubkey, // Importer or exporter; the other party must approve
+    pub deadline: i64, // Timestamp or duration, per the order's deadline_mode
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+// Deadline extension granted to the exporter
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct GrantedExtension {
+    pub previous_deadline: i64,
+    pub new_deadline: i64,
+    pub price_reduction: u64, // Refunded to importer as the price of the extension
+    pub timestamp: i64,
+}
+
+// Importer's conditions for granting a requested extension
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct ExtensionCounterOffer {
+    pub deadline: i64, // At most the requested extension deadline
+    pub price_reduction: u64,
+}
+
+// Optional per-order terms agreed at creation
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct OrderTerms {
+    pub bond_amount: u64, // Exporter performance bond, 0 = no bond
+    pub bond_slash_bps: u16, // Share of the bond paid to importer on a missed deadline
+    pub grace_period: i64, // Seconds after the deadline in which late delivery is still accepted
+    pub late_penalty_bps_per_day: u16, // Refunded to importer per (started) day late
+    pub acceptance_window: i64, // Seconds the exporter has to accept before the importer may reclaim funds
+    pub require_verifier_acceptance: bool, // Verifier must also accept before the order goes live
+    pub deadline_mode: DeadlineMode, // How proposed deadlines are interpreted
+    pub max_extensions: u8, // Number of extensions that may be granted, at most MAX_EXTENSIONS
+    pub max_total_extension: i64, // Cumulative seconds the deadline may be pushed back
+    pub extension_response_window: i64, // Seconds the importer has to answer an extension request
+    pub verifier_panel: Vec<Pubkey>, // Optional M-of-N panel replacing the single verifier, max MAX_VERIFIERS
+    pub verifier_threshold: u8, // Votes needed from the panel, 0 when there is no panel
+}
+
 // Order metadata for search/filter
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
 pub struct OrderMetadata {
@@ -30,34 +142,274 @@ pub struct OrderMetadata {
 pub mod escrow {
     use super::*;
 
+    pub fn initialize_config(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = *ctx.accounts.admin.key;
+        config.bump = ctx.bumps.config;
+        config.apply(params)?;
+        
+        emit!(config.updated_event());
+        Ok(())
+    }
+
+    pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.apply(params)?;
+        
+        emit!(config.updated_event());
+        Ok(())
+    }
+
+    pub fn set_config_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = new_admin;
+        
+        emit!(config.updated_event());
+        Ok(())
+    }
+
+    pub fn add_allowed_mint(ctx: Context<AddAllowedMint>, min_amount: u64, max_amount: u64, min_fee: u64) -> Result<()> {
+        require!(max_amount == 0 || min_amount <= max_amount, EscrowError::InvalidConfig);
+        let allowed_mint = &mut ctx.accounts.allowed_mint;
+        allowed_mint.mint = ctx.accounts.token_mint.key();
+        allowed_mint.enabled = true;
+        allowed_mint.min_amount = min_amount;
+        allowed_mint.max_amount = max_amount;
+        allowed_mint.min_fee = min_fee;
+        allowed_mint.bump = ctx.bumps.allowed_mint;
+        
+        emit!(AllowedMintUpdated {
+            version: EVENT_VERSION,
+            mint: allowed_mint.mint,
+            enabled: true,
+            min_amount,
+            max_amount,
+            min_fee,
+            removed: false,
+        });
+        Ok(())
+    }
+
+    pub fn disable_allowed_mint(ctx: Context<UpdateAllowedMint>) -> Result<()> {
+        let allowed_mint = &mut ctx.accounts.allowed_mint;
+        allowed_mint.enabled = false;
+        
+        emit!(AllowedMintUpdated {
+            version: EVENT_VERSION,
+            mint: allowed_mint.mint,
+            enabled: false,
+            min_amount: allowed_mint.min_amount,
+            max_amount: allowed_mint.max_amount,
+            min_fee: allowed_mint.min_fee,
+            removed: false,
+        });
+        Ok(())
+    }
+
+    pub fn remove_allowed_mint(ctx: Context<RemoveAllowedMint>) -> Result<()> {
+        let allowed_mint = &ctx.accounts.allowed_mint;
+        
+        emit!(AllowedMintUpdated {
+            version: EVENT_VERSION,
+            mint: allowed_mint.mint,
+            enabled: false,
+            min_amount: allowed_mint.min_amount,
+            max_amount: allowed_mint.max_amount,
+            min_fee: allowed_mint.min_fee,
+            removed: true,
+        });
+        Ok(())
+    }
+
+    pub fn register_verifier(ctx: Context<RegisterVerifier>, profile: VerifierProfile, stake: u64) -> Result<()> {
+        let verifier = &mut ctx.accounts.verifier_account;
+        verifier.authority = ctx.accounts.authority.key();
+        verifier.suspended = false;
+        verifier.stake = 0;
+        verifier.unbonding_amount = 0;
+        verifier.unbonding_until = 0;
+        verifier.bump = ctx.bumps.verifier_account;
+        verifier.set_profile(profile)?;
+        
+        // Stake is held as lamports in the registry account, on top of its rent
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.authority.to_account_info(),
+                    to: verifier.to_account_info(),
+                },
+            ),
+            stake,
+        )?;
+        verifier.stake = stake;
+        
+        emit!(VerifierUpdated {
+            version: EVENT_VERSION,
+            verifier: verifier.authority,
+            active: verifier.active,
+            suspended: false,
+            stake,
+        });
+        Ok(())
+    }
+
+    pub fn update_verifier(ctx: Context<UpdateVerifier>, profile: VerifierProfile, added_stake: u64) -> Result<()> {
+        let verifier = &mut ctx.accounts.verifier_account;
+        verifier.set_profile(profile)?;
+        // Taking orders again cancels a pending unbond
+        if verifier.active {
+            verifier.unbonding_amount = 0;
+            verifier.unbonding_until = 0;
+        }
+        
+        if added_stake > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.authority.to_account_info(),
+                        to: verifier.to_account_info(),
+                    },
+                ),
+                added_stake,
+            )?;
+            verifier.stake += added_stake;
+        }
+        
+        emit!(VerifierUpdated {
+            version: EVENT_VERSION,
+            verifier: verifier.authority,
+            active: verifier.active,
+            suspended: verifier.suspended,
+            stake: verifier.stake,
+        });
+        Ok(())
+    }
+
+    pub fn request_verifier_unbond(ctx: Context<UnbondVerifier>, amount: u64) -> Result<()> {
+        let verifier = &mut ctx.accounts.verifier_account;
+        // Stake backs the verifier's open work, so it can only leave once they stop taking orders
+        require!(!verifier.active, EscrowError::VerifierStillActive);
+        require!(!verifier.suspended, EscrowError::VerifierNotActive);
+        require!(amount > 0 && amount <= verifier.stake, EscrowError::InsufficientStake);
+        
+        // The stake stays in the account, and slashable, for the unbonding period
+        let now = Clock::get()?.unix_timestamp;
+        verifier.unbonding_amount = amount;
+        verifier.unbonding_until = now
+            .checked_add(ctx.accounts.config.verifier_unbonding_period)
+            .ok_or(EscrowError::InvalidConfig)?;
+        
+        emit!(VerifierUnbonding {
+            version: EVENT_VERSION,
+            verifier: verifier.authority,
+            amount,
+            unbonding_until: verifier.unbonding_until,
+        });
+        Ok(())
+    }
+
+    pub fn withdraw_verifier_stake(ctx: Context<UnbondVerifier>) -> Result<()> {
+        let verifier = &mut ctx.accounts.verifier_account;
+        require!(!verifier.active, EscrowError::VerifierStillActive);
+        require!(!verifier.suspended, EscrowError::VerifierNotActive);
+        require!(verifier.unbonding_amount > 0, EscrowError::NoUnbondingRequest);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= verifier.unbonding_until, EscrowError::UnbondingPeriodActive);
+        
+        // Slashing during the unbonding period comes out of the requested amount first
+        let amount = verifier.unbonding_amount.min(verifier.stake);
+        verifier.stake -= amount;
+        verifier.unbonding_amount = 0;
+        verifier.unbonding_until = 0;
+        **verifier.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.authority.try_borrow_mut_lamports()? += amount;
+        
+        emit!(VerifierUpdated {
+            version: EVENT_VERSION,
+            verifier: verifier.authority,
+            active: false,
+            suspended: false,
+            stake: verifier.stake,
+        });
+        Ok(())
+    }
+
+    pub fn slash_verifier(ctx: Context<SlashVerifier>, amount: u64, suspend: bool, reason: String) -> Result<()> {
+        let verifier = &mut ctx.accounts.verifier_account;
+        require!(amount <= verifier.stake, EscrowError::InsufficientStake);
+        
+        // Slashed stake goes to the protocol treasury
+        verifier.stake -= amount;
+        **verifier.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.treasury.try_borrow_mut_lamports()? += amount;
+        if suspend {
+            verifier.suspended = true;
+        }
+        
+        emit!(VerifierSlashed {
+            version: EVENT_VERSION,
+            verifier: verifier.authority,
+            amount,
+            remaining_stake: verifier.stake,
+            suspended: verifier.suspended,
+            reason,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    pub fn reinstate_verifier(ctx: Context<SlashVerifier>) -> Result<()> {
+        let verifier = &mut ctx.accounts.verifier_account;
+        verifier.suspended = false;
+        
+        emit!(VerifierUpdated {
+            version: EVENT_VERSION,
+            verifier: verifier.authority,
+            active: verifier.active,
+            suspended: false,
+            stake: verifier.stake,
+        });
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn create_sol_order(
         ctx: Context<CreateSolOrder>,
+        order_id: [u8; 32],
         exporter: Pubkey,
         verifier: Pubkey,
         amount: u64,
         proposed_deadline: i64,
-        creation_time: i64,
         metadata: OrderMetadata,
+        milestones: Vec<MilestoneParams>,
+        terms: OrderTerms,
     ) -> Result<()> {
+        let config = &ctx.accounts.config;
+        require!(!config.paused, EscrowError::ProgramPaused);
+        let now = Clock::get()?.unix_timestamp;
+        
         // Validate deadline range
-        let time_until_deadline = proposed_deadline - creation_time;
+        config.check_deadline(terms.deadline_mode.resolve(proposed_deadline, now)?, now)?;
         
-        require!(
-            time_until_deadline >= MIN_DEADLINE,
-            EscrowError::DeadlineTooShort
-        );
-        require!(
-            time_until_deadline <= MAX_DEADLINE,
-            EscrowError::DeadlineTooLong
-        );
+        // A registered verifier must be active and staked; the config can make registration mandatory
+        match ctx.accounts.verifier_account.as_ref() {
+            Some(registered) => registered.check_eligible(config)?,
+            None => require!(!config.require_registered_verifier, EscrowError::VerifierNotRegistered),
+        }
+        Verifier::check_panel(&terms.verifier_panel, ctx.remaining_accounts, config)?;
         
         let order = &mut ctx.accounts.order;
+        order.order_id = order_id;
+        order.bump = ctx.bumps.order;
         order.importer = *ctx.accounts.importer.key;
         order.exporter = exporter;
         order.verifier = verifier;
         order.amount = amount;
-        order.state = OrderState::PendingDeadlineApproval;
-        order.created_at = creation_time;
+        order.min_fee = ctx.accounts.config.min_fee;
+        order.state = OrderState::PendingAcceptance;
+        order.created_at = now;
         order.proposed_deadline = proposed_deadline;
         order.approved_deadline = 0;
         order.deadline_approved = false;
@@ -65,66 +417,114 @@ pub mod escrow {
         order.extension_deadline = 0;
         order.bill_of_lading_hash = [0u8; 32];
         order.token_mint = None; // Explicitly SOL transfer
+        order.escrow_bump = ctx.bumps.escrow_pda;
+        order.vault_bump = 0; // No token vault for SOL orders
         order.history = Vec::new();
         order.metadata = metadata;
-        order.last_updated = creation_time;
+        order.last_updated = now;
+        order.set_milestones(milestones, now)?;
+        order.set_terms(&terms, now, config)?;
+        order.proposals = Vec::new();
+        order.record_proposal(*ctx.accounts.importer.key, proposed_deadline, amount, now)?;
         
         order.add_history_entry(
-            OrderState::PendingDeadlineApproval,
-            "Order created - waiting for deadline approval".to_string(),
-            creation_time,
+            OrderState::PendingAcceptance,
+            "Order created - waiting for exporter acceptance".to_string(),
+            now,
         );
         
         // Transfer SOL
-        let ix = system_instruction::transfer(
-            ctx.accounts.importer.key,
-            ctx.accounts.escrow_pda.key,
-            amount,
+        ctx.accounts.escrow_pda.order = order.key();
+        let escrow_pda = ctx.accounts.escrow_pda.to_account_info();
+        let vault = EscrowVault::escrow(
+            order,
+            &escrow_pda,
+            None,
+            None,
+            None,
+            &[],
+            &ctx.accounts.system_program,
         );
-        invoke(
-            &ix,
-            &[
-                ctx.accounts.importer.to_account_info(),
-                ctx.accounts.escrow_pda.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-            ],
-        )?;
+        vault.deposit(&ctx.accounts.importer, None, amount)?;
+        
+        emit!(OrderCreated {
+            version: EVENT_VERSION,
+            order: order.key(),
+            importer: order.importer,
+            exporter: order.exporter,
+            verifier: order.verifier,
+            token_mint: order.token_mint,
+            amount,
+            proposed_deadline,
+            milestone_count: order.milestones.len() as u8,
+            timestamp: now,
+        });
         
         Ok(())
     }
 
-    pub fn create_spl_order(
-        ctx: Context<CreateSplOrder>,
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_spl_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateSplOrder<'info>>,
+        order_id: [u8; 32],
         exporter: Pubkey,
         verifier: Pubkey,
         amount: u64,
         proposed_deadline: i64,
-        creation_time: i64,
         metadata: OrderMetadata,
+        milestones: Vec<MilestoneParams>,
+        terms: OrderTerms,
     ) -> Result<()> {
         // This function now specifically handles SPL tokens.
         // The token_mint is derived from the context.
         let token_mint_key = ctx.accounts.token_mint.key();
 
+        let config = &ctx.accounts.config;
+        require!(!config.paused, EscrowError::ProgramPaused);
+        let now = Clock::get()?.unix_timestamp;
+        
         // Validate deadline range
-        let time_until_deadline = proposed_deadline - creation_time;
+        config.check_deadline(terms.deadline_mode.resolve(proposed_deadline, now)?, now)?;
         
-        require!(
-            time_until_deadline >= MIN_DEADLINE,
-            EscrowError::DeadlineTooShort
-        );
-        require!(
-            time_until_deadline <= MAX_DEADLINE,
-            EscrowError::DeadlineTooLong
-        );
+        // A registered verifier must be active and staked; the config can make registration mandatory
+        match ctx.accounts.verifier_account.as_ref() {
+            Some(registered) => registered.check_eligible(config)?,
+            None => require!(!config.require_registered_verifier, EscrowError::VerifierNotRegistered),
+        }
+        let hook_accounts = Verifier::check_panel(&terms.verifier_panel, ctx.remaining_accounts, config)?;
+        
+        // Transfer SPL tokens; with a Token-2022 transfer fee the order is for what the vault received.
+        // The order is not initialised yet, so the vault's seeds come from the context
+        let vault = EscrowVault {
+            seed: b"escrow_pda",
+            order_key: ctx.accounts.order.key(),
+            bump: ctx.bumps.escrow_pda,
+            token_mint: Some(token_mint_key),
+            authority: &ctx.accounts.escrow_pda,
+            token_vault: Some(&ctx.accounts.escrow_token_account),
+            token_program: Some(&ctx.accounts.token_program),
+            mint: Some(&ctx.accounts.token_mint),
+            remaining_accounts: hook_accounts,
+            system_program: &ctx.accounts.system_program,
+        };
+        let amount = vault.deposit(
+            &ctx.accounts.importer,
+            ctx.accounts.importer_token_account.as_ref(),
+            amount,
+        )?;
+        // The per-mint limits apply to what the vault received, net of any transfer fee
+        ctx.accounts.allowed_mint.check_amount(amount)?;
         
         let order = &mut ctx.accounts.order;
+        order.order_id = order_id;
+        order.bump = ctx.bumps.order;
         order.importer = *ctx.accounts.importer.key;
         order.exporter = exporter;
         order.verifier = verifier;
         order.amount = amount;
-        order.state = OrderState::PendingDeadlineApproval;
-        order.created_at = creation_time;
+        order.min_fee = ctx.accounts.allowed_mint.min_fee;
+        order.state = OrderState::PendingAcceptance;
+        order.created_at = now;
         order.proposed_deadline = proposed_deadline;
         order.approved_deadline = 0;
         order.deadline_approved = false;
@@ -132,27 +532,34 @@ pub mod escrow {
         order.extension_deadline = 0;
         order.bill_of_lading_hash = [0u8; 32];
         order.token_mint = Some(token_mint_key);
+        order.escrow_bump = ctx.bumps.escrow_pda;
+        order.vault_bump = ctx.bumps.escrow_token_account;
         order.history = Vec::new();
         order.metadata = metadata;
-        order.last_updated = creation_time;
+        order.last_updated = now;
+        order.set_milestones(milestones, now)?;
+        order.set_terms(&terms, now, config)?;
+        order.proposals = Vec::new();
+        order.record_proposal(*ctx.accounts.importer.key, proposed_deadline, amount, now)?;
         
         order.add_history_entry(
-            OrderState::PendingDeadlineApproval,
-            "Order created - waiting for deadline approval".to_string(),
-            creation_time,
+            OrderState::PendingAcceptance,
+            "Order created - waiting for exporter acceptance".to_string(),
+            now,
         );
         
-        // Transfer SPL tokens
-        let cpi_accounts = token::Transfer {
-            from: ctx.accounts.importer_token_account.to_account_info(),
-            to: ctx.accounts.escrow_token_account.to_account_info(),
-            authority: ctx.accounts.importer.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts,
-        );
-        token::transfer(cpi_ctx, amount)?;
+        emit!(OrderCreated {
+            version: EVENT_VERSION,
+            order: order.key(),
+            importer: order.importer,
+            exporter: order.exporter,
+            verifier: order.verifier,
+            token_mint: order.token_mint,
+            amount,
+            proposed_deadline,
+            milestone_count: order.milestones.len() as u8,
+            timestamp: now,
+        });
         
         Ok(())
     }
@@ -166,9 +573,9 @@ pub mod escrow {
         require!(order.exporter == *ctx.accounts.exporter.key, EscrowError::Unauthorized);
         require!(order.deadline_approved, EscrowError::DeadlineNotApproved);
         
-        // Check if deadline has passed
+        // Check if deadline (plus any grace period) has passed
         let now = Clock::get()?.unix_timestamp;
-        require!(now <= order.approved_deadline, EscrowError::DeadlinePassed);
+        require!(now <= order.final_deadline()?, EscrowError::DeadlinePassed);
         
         order.bill_of_lading_hash = bill_of_lading_hash;
         order.state = OrderState::InTransit;
@@ -180,19 +587,30 @@ pub mod escrow {
             now,
         );
         
+        emit!(GoodsShipped {
+            version: EVENT_VERSION,
+            order: order.key(),
+            bill_of_lading_hash,
+            timestamp: now,
+        });
+        
         Ok(())
     }
 
-    pub fn confirm_delivery(ctx: Context<ConfirmDelivery>) -> Result<()> {
+    pub fn confirm_delivery<'info>(ctx: Context<'_, '_, '_, 'info, ConfirmDelivery<'info>>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
         let order = &mut ctx.accounts.order;
         require!(order.state == OrderState::InTransit, EscrowError::InvalidState);
-        // Only verifier or importer can confirm delivery
+        // Only verifier (or a panel quorum) or importer can confirm delivery
         let signer = ctx.accounts.signer.key;
-        require!(signer == &order.verifier || signer == &order.importer, EscrowError::Unauthorized);
+        require!(
+            signer == &order.importer || order.verifier_approves(signer, &VerifierVote::ConfirmDelivery),
+            EscrowError::Unauthorized
+        );
         
-        // Check if deadline has passed
+        // Check if deadline (plus any grace period) has passed
         let now = Clock::get()?.unix_timestamp;
-        require!(now <= order.approved_deadline, EscrowError::DeadlinePassed);
+        require!(now <= order.final_deadline()?, EscrowError::DeadlinePassed);
         
         order.state = OrderState::Delivered;
         
@@ -203,53 +621,116 @@ pub mod escrow {
             now,
         );
         
-        // Automatically release funds to exporter when delivery is confirmed
-        let amount = order.amount;
-        match order.token_mint {
-            None => {
-                let ix = system_instruction::transfer(
-                    ctx.accounts.escrow_pda.key,
-                    ctx.accounts.exporter.key,
-                    amount,
-                );
-                let order_key = order.key();
-                let seeds = &[
-                    b"escrow_pda",
-                    order_key.as_ref(),
-                    &[ctx.bumps.escrow_pda],
-                ];
-                let signer = &[&seeds[..]];
-                invoke_signed(
-                    &ix,
-                    &[
-                        ctx.accounts.escrow_pda.to_account_info(),
-                        ctx.accounts.exporter.to_account_info(),
-                        ctx.accounts.system_program.to_account_info(),
-                    ],
-                    signer,
-                )?;
-            },
-            Some(_mint) => {
-                let cpi_accounts = token::Transfer {
-                    from: ctx.accounts.escrow_token_account.as_ref().unwrap().to_account_info(),
-                    to: ctx.accounts.exporter_token_account.as_ref().unwrap().to_account_info(),
-                    authority: ctx.accounts.escrow_pda.to_account_info(),
-                };
-                let order_key = order.key();
-                let seeds = &[
-                    b"escrow_pda",
-                    order_key.as_ref(),
-                    &[ctx.bumps.escrow_pda],
-                ];
-                let signer = &[&seeds[..]];
-                let cpi_ctx = CpiContext::new_with_signer(
-                    ctx.accounts.token_program.as_ref().unwrap().to_account_info(),
-                    cpi_accounts,
-                    signer,
-                );
-                token::transfer(cpi_ctx, amount)?;
-            }
+        emit!(DeliveryConfirmed {
+            version: EVENT_VERSION,
+            order: order.key(),
+            confirmed_by: *ctx.accounts.signer.key,
+            timestamp: now,
+        });
+        
+        // Return the exporter's bond now that delivery is confirmed
+        if order.bond_posted {
+            let (to_exporter, to_importer) = order.bond_split(false);
+            let bond_vault = EscrowVault::bond(
+                order,
+                ctx.accounts.bond_pda.as_ref().ok_or(EscrowError::MissingBondAccount)?,
+                ctx.accounts.bond_token_account.as_ref(),
+                ctx.accounts.token_program.as_ref(),
+                ctx.accounts.token_mint.as_ref(),
+                ctx.remaining_accounts,
+                &ctx.accounts.system_program,
+            );
+            bond_vault.pay(
+                &ctx.accounts.exporter,
+                ctx.accounts.exporter_token_account.as_ref(),
+                to_exporter,
+            )?;
+            bond_vault.close_vault(
+                &ctx.accounts.exporter,
+                ctx.accounts.exporter_token_account.as_ref(),
+            )?;
+            order.settle_bond(to_exporter, to_importer, now);
+            
+            emit!(BondSettled {
+                version: EVENT_VERSION,
+                order: order.key(),
+                returned_to_exporter: to_exporter,
+                slashed_to_importer: to_importer,
+                timestamp: now,
+            });
+        }
+        
+        // Milestone schedules pay out through their own approvals
+        if !order.milestones.is_empty() {
+            return Ok(());
+        }
+        
+        // Late deliveries inside the grace period refund the liquidated damages to importer
+        let remaining = order.amount - order.released_amount - order.refunded_amount;
+        let (days_late, penalty) = order.late_penalty(remaining, now);
+        
+        // Automatically release the rest of the escrow to exporter when delivery is confirmed,
+        // less the protocol fee which goes to the treasury
+        let amount = remaining - penalty;
+        let fee = ctx.accounts.config.protocol_fee(amount, order.min_fee);
+        let vault = EscrowVault::escrow(
+            order,
+            &ctx.accounts.escrow_pda,
+            ctx.accounts.escrow_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        vault.pay(
+            &ctx.accounts.exporter,
+            ctx.accounts.exporter_token_account.as_ref(),
+            amount - fee,
+        )?;
+        vault.pay(
+            &ctx.accounts.treasury,
+            ctx.accounts.treasury_token_account.as_ref(),
+            fee,
+        )?;
+        if penalty > 0 {
+            vault.pay(
+                ctx.accounts.importer.as_ref().ok_or(EscrowError::MissingPenaltyAccount)?,
+                ctx.accounts.importer_token_account.as_ref(),
+                penalty,
+            )?;
+            order.refunded_amount += penalty;
+            let current_state = order.state.clone();
+            let penalty_bps_per_day = order.late_penalty_bps_per_day;
+            order.add_history_entry(
+                current_state,
+                format!(
+                    "Late penalty: {} day(s) x {} bps = {} units refunded to importer",
+                    days_late, penalty_bps_per_day, penalty
+                ),
+                now,
+            );
+            
+            emit!(LatePenaltyApplied {
+                version: EVENT_VERSION,
+                order: order.key(),
+                days_late,
+                penalty_bps_per_day,
+                penalty,
+                timestamp: now,
+            });
         }
+        order.released_amount += amount;
+        order.record_fee(fee, now);
+        
+        emit!(FundsReleased {
+            version: EVENT_VERSION,
+            order: order.key(),
+            amount: amount - fee,
+            fee,
+            milestone: None,
+            total_released: order.released_amount,
+            timestamp: now,
+        });
         order.state = OrderState::Completed;
         
         // Add history entry for completion
@@ -262,144 +743,256 @@ pub mod escrow {
         Ok(())
     }
 
-    pub fn check_deadline_and_refund(ctx: Context<CheckDeadlineAndRefund>, current_time: i64) -> Result<()> {
+    pub fn check_deadline_and_refund<'info>(ctx: Context<'_, '_, '_, 'info, CheckDeadlineAndRefund<'info>>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let now = Clock::get()?.unix_timestamp;
         let order = &mut ctx.accounts.order;
-        require!(order.state != OrderState::Completed, EscrowError::InvalidState);
+        require!(
+            order.state != OrderState::Completed
+                && order.state != OrderState::Refunded
+                && order.state != OrderState::Cancelled
+                // A disputed order settles only through the verifier's resolve_dispute
+                && order.state != OrderState::Disputed,
+            EscrowError::InvalidState
+        );
         require!(order.deadline_approved, EscrowError::DeadlineNotApproved);
         
-        // Use the current_time parameter from CLI to ensure consistency
-        require!(current_time > order.approved_deadline, EscrowError::TooEarlyForRefund);
+        require!(now > order.final_deadline()?, EscrowError::TooEarlyForRefund);
+        // Approved milestones are owed to the exporter; unpaid ones go back via refund_milestone
+        require!(order.milestones.is_empty(), EscrowError::MilestoneScheduleActive);
         
-        // Automatically refund to importer if deadline has passed and goods not delivered
-        let amount = order.amount;
-        match order.token_mint {
-            None => {
-                let ix = system_instruction::transfer(
-                    ctx.accounts.escrow_pda.key,
-                    ctx.accounts.importer.key,
-                    amount,
-                );
-                let order_key = order.key();
-                let seeds = &[
-                    b"escrow_pda",
-                    order_key.as_ref(),
-                    &[ctx.bumps.escrow_pda],
-                ];
-                let signer = &[&seeds[..]];
-                invoke_signed(
-                    &ix,
-                    &[
-                        ctx.accounts.escrow_pda.to_account_info(),
-                        ctx.accounts.importer.to_account_info(),
-                        ctx.accounts.system_program.to_account_info(),
-                    ],
-                    signer,
-                )?;
-            },
-            Some(_mint) => {
-                let cpi_accounts = token::Transfer {
-                    from: ctx.accounts.escrow_token_account.as_ref().unwrap().to_account_info(),
-                    to: ctx.accounts.importer_token_account.as_ref().unwrap().to_account_info(),
-                    authority: ctx.accounts.escrow_pda.to_account_info(),
-                };
-                let order_key = order.key();
-                let seeds = &[
-                    b"escrow_pda",
-                    order_key.as_ref(),
-                    &[ctx.bumps.escrow_pda],
-                ];
-                let signer = &[&seeds[..]];
-                let cpi_ctx = CpiContext::new_with_signer(
-                    ctx.accounts.token_program.as_ref().unwrap().to_account_info(),
-                    cpi_accounts,
-                    signer,
-                );
-                token::transfer(cpi_ctx, amount)?;
-            }
-        }
+        // Automatically refund whatever is still in escrow to importer if deadline has passed
+        // and goods not delivered
+        let amount = order.amount - order.released_amount - order.refunded_amount;
+        let vault = EscrowVault::escrow(
+            order,
+            &ctx.accounts.escrow_pda,
+            ctx.accounts.escrow_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        vault.pay(
+            &ctx.accounts.importer,
+            ctx.accounts.importer_token_account.as_ref(),
+            amount,
+        )?;
+        order.refunded_amount += amount;
         order.state = OrderState::Refunded;
         
         // Add history entry
         order.add_history_entry(
             OrderState::Refunded,
             "Deadline passed - funds refunded to importer".to_string(),
-            current_time,
+            now,
         );
         
-        // Debug: Print the values
-        msg!("Debug - Current time: {}", current_time);
-        msg!("Debug - Approved deadline: {}", order.approved_deadline);
-        msg!("Debug - Time difference: {}", current_time - order.approved_deadline);
+        // The exporter missed the deadline, so the bond is slashed in the importer's favour
+        if order.bond_posted {
+            let (to_exporter, to_importer) = order.bond_split(true);
+            let exporter = ctx.accounts.exporter.as_ref().ok_or(EscrowError::MissingBondAccount)?;
+            let bond_vault = EscrowVault::bond(
+                order,
+                ctx.accounts.bond_pda.as_ref().ok_or(EscrowError::MissingBondAccount)?,
+                ctx.accounts.bond_token_account.as_ref(),
+                ctx.accounts.token_program.as_ref(),
+                ctx.accounts.token_mint.as_ref(),
+                ctx.remaining_accounts,
+                &ctx.accounts.system_program,
+            );
+            bond_vault.pay(
+                &ctx.accounts.importer,
+                ctx.accounts.importer_token_account.as_ref(),
+                to_importer,
+            )?;
+            bond_vault.pay(
+                exporter,
+                ctx.accounts.exporter_token_account.as_ref(),
+                to_exporter,
+            )?;
+            bond_vault.close_vault(exporter, ctx.accounts.exporter_token_account.as_ref())?;
+            order.settle_bond(to_exporter, to_importer, now);
+            
+            emit!(BondSettled {
+                version: EVENT_VERSION,
+                order: order.key(),
+                returned_to_exporter: to_exporter,
+                slashed_to_importer: to_importer,
+                timestamp: now,
+            });
+        }
+        
+        emit!(Refunded {
+            version: EVENT_VERSION,
+            order: order.key(),
+            amount,
+            milestone: None,
+            total_refunded: order.refunded_amount,
+            timestamp: now,
+        });
         
         Ok(())
     }
 
-    pub fn approve_deadline(ctx: Context<ApproveDeadline>, current_time: i64) -> Result<()> {
+    pub fn approve_deadline<'info>(ctx: Context<'_, '_, '_, 'info, ApproveDeadline<'info>>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let now = Clock::get()?.unix_timestamp;
         let order = &mut ctx.accounts.order;
         require!(order.state == OrderState::PendingDeadlineApproval, EscrowError::InvalidState);
-        require!(order.importer == *ctx.accounts.importer.key, EscrowError::Unauthorized);
         
-        // The proposed_deadline is an absolute timestamp from the CLI (local time)
-        // We need to extract the duration and apply it from the approval time
-        let creation_time = order.created_at;
-        let proposed_duration = order.proposed_deadline - creation_time;
+        // Only the counterparty of the latest proposal can approve it
+        let proposal = order.proposals.last().cloned().ok_or(EscrowError::InvalidState)?;
+        let signer = ctx.accounts.signer.key();
+        require!(
+            (signer == order.importer || signer == order.exporter) && signer != proposal.proposer,
+            EscrowError::Unauthorized
+        );
+        // The exporter accepts the deadline by posting the agreed bond
+        require!(order.bond_amount == 0 || order.bond_posted, EscrowError::BondNotPosted);
+        
+        // Approve exactly the agreed terms: an absolute deadline as proposed, or a relative one
+        // counted from now (approval time), and re-check it against the config bounds
+        let approved_deadline = order.deadline_mode.resolve(order.proposed_deadline, now)?;
+        ctx.accounts.config.check_deadline(approved_deadline, now)?;
+        
+        // Settle any agreed change in amount against what is escrowed: an increase is pulled
+        // from the importer (who is signing, as the exporter proposed it), a decrease refunded
+        let vault = EscrowVault::escrow(
+            order,
+            &ctx.accounts.escrow_pda,
+            ctx.accounts.escrow_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        let previous_amount = order.amount;
+        if proposal.amount > order.amount {
+            order.amount += vault.deposit(
+                &ctx.accounts.importer,
+                ctx.accounts.importer_token_account.as_ref(),
+                proposal.amount - order.amount,
+            )?;
+        } else {
+            vault.pay(
+                &ctx.accounts.importer,
+                ctx.accounts.importer_token_account.as_ref(),
+                order.amount - proposal.amount,
+            )?;
+            order.amount = proposal.amount;
+        }
         
-        // Set the approved deadline to start from now (approval time) with the same duration
-        // Use the current_time parameter from CLI to ensure consistency
-        order.approved_deadline = current_time + proposed_duration;
+        order.approved_deadline = approved_deadline;
         order.deadline_approved = true;
         order.state = OrderState::PendingShipment;
         
         // Add history entry
+        let amount = order.amount;
         order.add_history_entry(
             OrderState::PendingShipment,
-            "Deadline approved by importer - ready for shipment".to_string(),
-            current_time,
+            if previous_amount != amount {
+                format!(
+                    "Terms approved - amount {} -> {}, ready for shipment",
+                    previous_amount, amount
+                )
+            } else {
+                "Deadline approved - ready for shipment".to_string()
+            },
+            now,
         );
         
-        // Debug: Print the values (this will be in the transaction logs)
-        msg!("Debug - Creation time: {}", creation_time);
-        msg!("Debug - Proposed deadline: {}", order.proposed_deadline);
-        msg!("Debug - Proposed duration: {}", proposed_duration);
-        msg!("Debug - Approval time: {}", current_time);
-        msg!("Debug - Approved deadline: {}", order.approved_deadline);
+        emit!(DeadlineApproved {
+            version: EVENT_VERSION,
+            order: order.key(),
+            approved_by: signer,
+            proposed_deadline: order.proposed_deadline,
+            approved_deadline: order.approved_deadline,
+            amount: order.amount,
+            timestamp: now,
+        });
         
         Ok(())
     }
 
-    pub fn propose_new_deadline(ctx: Context<ProposeNewDeadline>, new_deadline: i64) -> Result<()> {
+    pub fn propose_new_deadline<'info>(ctx: Context<'_, '_, '_, 'info, ProposeNewDeadline<'info>>, new_deadline: i64, new_amount: u64) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
         let order = &mut ctx.accounts.order;
         require!(order.state == OrderState::PendingDeadlineApproval, EscrowError::InvalidState);
-        require!(order.exporter == *ctx.accounts.exporter.key, EscrowError::Unauthorized);
+        let proposer = ctx.accounts.proposer.key();
+        require!(proposer == order.importer || proposer == order.exporter, EscrowError::Unauthorized);
         
         // Validate new deadline range
         let now = Clock::get()?.unix_timestamp;
-        let time_until_deadline = new_deadline - now;
+        ctx.accounts.config.check_deadline(order.deadline_mode.resolve(new_deadline, now)?, now)?;
         
+        // Milestone amounts are fixed at creation, so the total cannot be renegotiated
+        require!(new_amount > 0, EscrowError::InvalidPartialAmount);
         require!(
-            time_until_deadline >= MIN_DEADLINE,
-            EscrowError::DeadlineTooShort
-        );
-        require!(
-            time_until_deadline <= MAX_DEADLINE,
-            EscrowError::DeadlineTooLong
+            order.milestones.is_empty() || new_amount == order.amount,
+            EscrowError::MilestoneScheduleActive
         );
+        // A new amount on an SPL order must stay within the mint's limits
+        if order.token_mint.is_some() && new_amount != order.amount {
+            let allowed_mint = ctx.accounts.allowed_mint.as_ref().ok_or(EscrowError::MintNotAllowed)?;
+            allowed_mint.check_amount(new_amount)?;
+        }
         
+        // An importer offering more tops up the escrow now, so whatever the exporter
+        // approves is always covered by the vault
+        let new_amount = if proposer == order.importer && new_amount > order.amount {
+            let vault = EscrowVault::escrow(
+                order,
+                &ctx.accounts.escrow_pda,
+                ctx.accounts.escrow_token_account.as_ref(),
+                ctx.accounts.token_program.as_ref(),
+                ctx.accounts.token_mint.as_ref(),
+                ctx.remaining_accounts,
+                &ctx.accounts.system_program,
+            );
+            order.amount += vault.deposit(
+                &ctx.accounts.proposer,
+                ctx.accounts.importer_token_account.as_ref(),
+                new_amount - order.amount,
+            )?;
+            // With a Token-2022 transfer fee the round offers what the vault actually received
+            order.amount
+        } else {
+            new_amount
+        };
+        
+        order.record_proposal(proposer, new_deadline, new_amount, now)?;
         order.proposed_deadline = new_deadline;
         order.deadline_approved = false;
         order.approved_deadline = 0;
         
         // Add history entry
+        let role = if proposer == order.importer { "importer" } else { "exporter" };
+        let round = order.proposals.len() as u8;
         order.add_history_entry(
             OrderState::PendingDeadlineApproval,
-            "New deadline proposed by exporter - waiting for approval".to_string(),
+            format!(
+                "Round {}: {} proposed deadline {} for {} units - waiting for approval",
+                round, role, new_deadline, new_amount
+            ),
             now,
         );
         
+        emit!(DeadlineProposed {
+            version: EVENT_VERSION,
+            order: order.key(),
+            proposed_by: proposer,
+            proposed_deadline: new_deadline,
+            amount: new_amount,
+            round,
+            timestamp: now,
+        });
+        
         Ok(())
     }
 
-    pub fn request_deadline_extension(ctx: Context<RequestDeadlineExtension>, new_deadline: i64, current_time: i64) -> Result<()> {
+    pub fn request_deadline_extension(ctx: Context<RequestDeadlineExtension>, new_deadline: i64, simulated_time: Option<i64>) -> Result<()> {
+        let now = ctx.accounts.config.resolve_time(simulated_time)?;
         let order = &mut ctx.accounts.order;
         require!(order.state == OrderState::PendingShipment || order.state == OrderState::InTransit, EscrowError::InvalidState);
         require!(order.exporter == *ctx.accounts.exporter.key, EscrowError::Unauthorized);
@@ -407,60 +1000,109 @@ pub mod escrow {
         require!(!order.extension_requested, EscrowError::ExtensionAlreadyRequested);
         
         // Validate extension deadline range
-        let time_until_deadline = new_deadline - current_time;
+        ctx.accounts.config.check_deadline(new_deadline, now)?;
+        require!(new_deadline > order.approved_deadline, EscrowError::InvalidExtension);
         
+        // Enforce the per-order extension policy
         require!(
-            time_until_deadline >= MIN_DEADLINE,
-            EscrowError::DeadlineTooShort
+            order.extensions.len() < order.max_extensions as usize,
+            EscrowError::ExtensionLimitReached
         );
         require!(
-            time_until_deadline <= MAX_DEADLINE,
-            EscrowError::DeadlineTooLong
+            order.total_extension() + (new_deadline - order.approved_deadline) <= order.max_total_extension,
+            EscrowError::ExtensionLimitReached
         );
         
         order.extension_requested = true;
         order.extension_deadline = new_deadline;
+        order.extension_response_deadline = now
+            .checked_add(order.extension_response_window)
+            .ok_or(EscrowError::TimeOverflow)?;
         order.state = OrderState::PendingExtensionApproval;
         
         // Add history entry
         order.add_history_entry(
             OrderState::PendingExtensionApproval,
             "Deadline extension requested by exporter - waiting for approval".to_string(),
-            current_time,
+            now,
         );
         
+        emit!(ExtensionRequested {
+            version: EVENT_VERSION,
+            order: order.key(),
+            current_deadline: order.approved_deadline,
+            extension_deadline: new_deadline,
+            timestamp: now,
+        });
+        
         Ok(())
     }
 
-    pub fn approve_deadline_extension(ctx: Context<ApproveDeadlineExtension>, current_time: i64) -> Result<()> {
+    pub fn approve_deadline_extension(
+        ctx: Context<ApproveDeadlineExtension>,
+        counter_offer: Option<ExtensionCounterOffer>,
+        simulated_time: Option<i64>,
+    ) -> Result<()> {
+        let now = ctx.accounts.config.resolve_time(simulated_time)?;
         let order = &mut ctx.accounts.order;
         require!(order.state == OrderState::PendingExtensionApproval, EscrowError::InvalidState);
         require!(order.importer == *ctx.accounts.importer.key, EscrowError::Unauthorized);
         require!(order.extension_requested, EscrowError::ExtensionRequestNotFound);
+        require!(now <= order.extension_response_deadline, EscrowError::ExtensionExpired);
         
-        // Update the approved deadline to the extension deadline
-        order.approved_deadline = order.extension_deadline;
-        order.extension_requested = false;
-        order.extension_deadline = 0;
+        // A counter-offer (shorter extension and/or price reduction) waits for the exporter
+        if let Some(offer) = counter_offer {
+            require!(
+                offer.deadline > order.approved_deadline && offer.deadline <= order.extension_deadline,
+                EscrowError::InvalidExtension
+            );
+            let remaining = order.amount - order.released_amount - order.refunded_amount;
+            require!(offer.price_reduction < remaining, EscrowError::InvalidPartialAmount);
+            require!(
+                order.milestones.is_empty() || offer.price_reduction == 0,
+                EscrowError::MilestoneScheduleActive
+            );
+            order.add_history_entry(
+                OrderState::PendingExtensionApproval,
+                format!(
+                    "Importer counter-offer: deadline {}, price reduction {}",
+                    offer.deadline, offer.price_reduction
+                ),
+                now,
+            );
+            
+            emit!(ExtensionCounterOffered {
+                version: EVENT_VERSION,
+                order: order.key(),
+                requested_deadline: order.extension_deadline,
+                counter_deadline: offer.deadline,
+                price_reduction: offer.price_reduction,
+                timestamp: now,
+            });
+            order.extension_counter = Some(offer);
+            return Ok(());
+        }
         
-        // Return to previous state (PendingShipment or InTransit)
-        let new_state = if order.bill_of_lading_hash != [0u8; 32] {
-            OrderState::InTransit
-        } else {
-            OrderState::PendingShipment
-        };
-        order.state = new_state.clone();
+        // Update the approved deadline to the extension deadline and
+        // return to previous state (PendingShipment or InTransit)
+        let extension_deadline = order.extension_deadline;
+        order.grant_extension(extension_deadline, 0, now);
         
         // Add history entry
+        let new_state = order.state.clone();
         order.add_history_entry(
             new_state,
             "Deadline extension approved by importer".to_string(),
-            current_time,
+            now,
         );
         
-        // Debug: Print the values
-        msg!("Debug - Extension approved at: {}", current_time);
-        msg!("Debug - New approved deadline: {}", order.approved_deadline);
+        emit!(ExtensionApproved {
+            version: EVENT_VERSION,
+            order: order.key(),
+            approved_deadline: order.approved_deadline,
+            price_reduction: 0,
+            timestamp: now,
+        });
         
         Ok(())
     }
@@ -471,17 +1113,10 @@ pub mod escrow {
         require!(order.importer == *ctx.accounts.importer.key, EscrowError::Unauthorized);
         require!(order.extension_requested, EscrowError::ExtensionRequestNotFound);
         
-        // Clear extension request
-        order.extension_requested = false;
-        order.extension_deadline = 0;
-        
-        // Return to previous state (PendingShipment or InTransit)
-        let new_state = if order.bill_of_lading_hash != [0u8; 32] {
-            OrderState::InTransit
-        } else {
-            OrderState::PendingShipment
-        };
-        order.state = new_state.clone();
+        // Clear extension request and return to previous state (PendingShipment or InTransit)
+        let rejected_deadline = order.extension_deadline;
+        order.clear_extension_request();
+        let new_state = order.state.clone();
         
         // Add history entry
         let now = Clock::get()?.unix_timestamp;
@@ -491,138 +1126,341 @@ pub mod escrow {
             now,
         );
         
+        emit!(ExtensionRejected {
+            version: EVENT_VERSION,
+            order: order.key(),
+            rejected_deadline,
+            timestamp: now,
+        });
+        
         Ok(())
     }
 
-    // Bulk operations and order management functions
-    pub fn update_order_metadata(ctx: Context<UpdateOrderMetadata>, metadata: OrderMetadata, current_time: i64) -> Result<()> {
+    pub fn accept_extension_counter_offer<'info>(ctx: Context<'_, '_, '_, 'info, AcceptExtensionCounterOffer<'info>>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let now = Clock::get()?.unix_timestamp;
         let order = &mut ctx.accounts.order;
-        // Only importer or exporter can update metadata
-        let signer = ctx.accounts.signer.key;
-        require!(signer == &order.importer || signer == &order.exporter, EscrowError::Unauthorized);
+        require!(order.state == OrderState::PendingExtensionApproval, EscrowError::InvalidState);
+        let offer = order.extension_counter.clone().ok_or(EscrowError::ExtensionRequestNotFound)?;
+        require!(now <= order.extension_response_deadline, EscrowError::ExtensionExpired);
         
-        let current_state = order.state.clone();
-        order.update_metadata(metadata, current_time);
+        // The price reduction is refunded to the importer out of the escrow
+        let vault = EscrowVault::escrow(
+            order,
+            &ctx.accounts.escrow_pda,
+            ctx.accounts.escrow_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        vault.pay(
+            &ctx.accounts.importer,
+            ctx.accounts.importer_token_account.as_ref(),
+            offer.price_reduction,
+        )?;
+        order.grant_extension(offer.deadline, offer.price_reduction, now);
         
         // Add history entry
+        let new_state = order.state.clone();
         order.add_history_entry(
-            current_state,
-            "Order metadata updated".to_string(),
-            current_time,
+            new_state,
+            format!(
+                "Exporter accepted counter-offer: deadline {}, {} refunded to importer",
+                offer.deadline, offer.price_reduction
+            ),
+            now,
         );
         
+        emit!(ExtensionApproved {
+            version: EVENT_VERSION,
+            order: order.key(),
+            approved_deadline: order.approved_deadline,
+            price_reduction: offer.price_reduction,
+            timestamp: now,
+        });
+        
         Ok(())
     }
 
-    pub fn bulk_check_deadlines(_ctx: Context<BulkCheckDeadlines>, current_time: i64) -> Result<()> {
-        // This function will be called by the CLI to check multiple orders
-        // The actual refund logic is handled in check_deadline_and_refund for individual orders
-        // This is just a placeholder for bulk operations
-        msg!("Bulk deadline check initiated at: {}", current_time);
+    // Permissionless crank: auto-reject an extension request left unanswered past its window
+    pub fn expire_deadline_extension(ctx: Context<ExpireDeadlineExtension>) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        require!(order.state == OrderState::PendingExtensionApproval, EscrowError::InvalidState);
+        require!(order.extension_requested, EscrowError::ExtensionRequestNotFound);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now > order.extension_response_deadline, EscrowError::ExtensionResponseWindowOpen);
+        
+        // Clear extension request and return to previous state (PendingShipment or InTransit)
+        let expired_deadline = order.extension_deadline;
+        order.clear_extension_request();
+        let new_state = order.state.clone();
+        
+        // Add history entry
+        order.add_history_entry(
+            new_state,
+            "Deadline extension request expired without an answer".to_string(),
+            now,
+        );
+        
+        emit!(ExtensionExpired {
+            version: EVENT_VERSION,
+            order: order.key(),
+            expired_deadline,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+
+    // Bulk operations and order management functions
+    pub fn update_order_metadata(ctx: Context<UpdateOrderMetadata>, metadata: OrderMetadata, simulated_time: Option<i64>) -> Result<()> {
+        let now = ctx.accounts.config.resolve_time(simulated_time)?;
+        let order = &mut ctx.accounts.order;
+        // Only importer or exporter can update metadata
+        let signer = ctx.accounts.signer.key;
+        require!(signer == &order.importer || signer == &order.exporter, EscrowError::Unauthorized);
+        
+        let current_state = order.state.clone();
+        order.update_metadata(metadata, now);
+        
+        // Add history entry
+        order.add_history_entry(
+            current_state,
+            "Order metadata updated".to_string(),
+            now,
+        );
+        
+        emit!(MetadataUpdated {
+            version: EVENT_VERSION,
+            order: order.key(),
+            updated_by: *signer,
+            timestamp: now,
+        });
+        
         Ok(())
     }
 
-    pub fn dispute_order(ctx: Context<DisputeOrder>, reason: String, current_time: i64) -> Result<()> {
+    pub fn bulk_check_deadlines(_ctx: Context<BulkCheckDeadlines>) -> Result<()> {
+        // This function will be called by the CLI to check multiple orders
+        // The actual refund logic is handled in check_deadline_and_refund for individual orders
+        // This is just a placeholder for bulk operations
+        let now = Clock::get()?.unix_timestamp;
+        msg!("Bulk deadline check initiated at: {}", now);
+        Ok(())
+    }
+
+    pub fn dispute_order(ctx: Context<DisputeOrder>, reason: String, simulated_time: Option<i64>) -> Result<()> {
+        let now = ctx.accounts.config.resolve_time(simulated_time)?;
         let order = &mut ctx.accounts.order;
         // Only importer or exporter can dispute
         let signer = ctx.accounts.signer.key;
         require!(signer == &order.importer || signer == &order.exporter, EscrowError::Unauthorized);
-        require!(order.state != OrderState::Completed && order.state != OrderState::Refunded, EscrowError::InvalidState);
+        require!(
+            order.state != OrderState::PendingAcceptance
+                && order.state != OrderState::Completed
+                && order.state != OrderState::Refunded
+                && order.state != OrderState::Cancelled,
+            EscrowError::InvalidState
+        );
         
         order.state = OrderState::Disputed;
         
         // Add history entry
         order.add_history_entry(
             OrderState::Disputed,
-            format!("Order disputed: {}", if reason.len() > 80 { &reason[..80] } else { &reason }),
-            current_time,
+            format!("Order disputed: {}", truncate_at_char_boundary(&reason, 80)),
+            now,
         );
         
+        emit!(Disputed {
+            version: EVENT_VERSION,
+            order: order.key(),
+            disputed_by: *signer,
+            reason,
+            timestamp: now,
+        });
+        
         Ok(())
     }
 
-    pub fn resolve_dispute(ctx: Context<ResolveDispute>, resolution: String, current_time: i64) -> Result<()> {
+    pub fn resolve_dispute<'info>(
+        ctx: Context<'_, '_, '_, 'info, ResolveDispute<'info>>,
+        exporter_bps: u16,
+        resolution: String,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let now = Clock::get()?.unix_timestamp;
         let order = &mut ctx.accounts.order;
-        // Only verifier can resolve disputes
-        require!(order.verifier == *ctx.accounts.verifier.key, EscrowError::Unauthorized);
+        // Only verifier (or a panel quorum for this exact split) can resolve disputes
+        require!(
+            order.verifier_approves(ctx.accounts.verifier.key, &VerifierVote::ResolveDispute { exporter_bps }),
+            EscrowError::Unauthorized
+        );
         require!(order.state == OrderState::Disputed, EscrowError::InvalidState);
+        require!(exporter_bps as u64 <= BPS_DENOMINATOR, EscrowError::InvalidSplit);
         
-        // For now, we'll just mark as completed (verifier decided in favor of exporter)
-        // In a real implementation, you might want more sophisticated dispute resolution
-        order.state = OrderState::Completed;
+        let (exporter_share, importer_share) = order.dispute_split(exporter_bps);
+        let fee = ctx.accounts.config.protocol_fee(exporter_share, order.min_fee);
+        
+        let vault = EscrowVault::escrow(
+            order,
+            &ctx.accounts.escrow_pda,
+            ctx.accounts.escrow_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        vault.pay(
+            &ctx.accounts.exporter,
+            ctx.accounts.exporter_token_account.as_ref(),
+            exporter_share - fee,
+        )?;
+        vault.pay(
+            &ctx.accounts.treasury,
+            ctx.accounts.treasury_token_account.as_ref(),
+            fee,
+        )?;
+        vault.pay(
+            &ctx.accounts.importer,
+            ctx.accounts.importer_token_account.as_ref(),
+            importer_share,
+        )?;
+        
+        order.released_amount += exporter_share;
+        order.refunded_amount += importer_share;
+        order.state = if order.released_amount > 0 { OrderState::Completed } else { OrderState::Refunded };
+        let final_state = order.state.clone();
+        
+        // Add history entries
+        order.add_history_entry(
+            final_state.clone(),
+            format!(
+                "Dispute verdict: {} units to exporter, {} units to importer ({} bps)",
+                exporter_share, importer_share, exporter_bps
+            ),
+            now,
+        );
+        order.add_history_entry(
+            final_state,
+            format!("Dispute resolved: {}", truncate_at_char_boundary(&resolution, 80)),
+            now,
+        );
+        order.record_fee(fee, now);
+        
+        emit!(DisputeResolved {
+            version: EVENT_VERSION,
+            order: order.key(),
+            exporter_bps,
+            exporter_amount: exporter_share - fee,
+            importer_amount: importer_share,
+            fee,
+            resolution,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+
+    pub fn submit_verifier_vote(ctx: Context<SubmitVerifierVote>, vote: VerifierVote) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let order = &mut ctx.accounts.order;
+        let signer = ctx.accounts.verifier.key();
+        let position = order
+            .verifier_panel
+            .iter()
+            .position(|v| *v == signer)
+            .ok_or(EscrowError::Unauthorized)?;
+        
+        // Votes only count towards the decision the order is waiting on
+        match vote {
+            VerifierVote::ConfirmDelivery => require!(order.state == OrderState::InTransit, EscrowError::InvalidState),
+            VerifierVote::ResolveDispute { exporter_bps } => {
+                require!(order.state == OrderState::Disputed, EscrowError::InvalidState);
+                require!(exporter_bps as u64 <= BPS_DENOMINATOR, EscrowError::InvalidSplit);
+            }
+        }
+        
+        // A verifier may change their vote; the latest one counts
+        order.verifier_votes[position] = Some(vote.clone());
+        let votes = order.panel_votes_for(&vote);
         
         // Add history entry
+        let now = Clock::get()?.unix_timestamp;
+        let current_state = order.state.clone();
+        let threshold = order.verifier_threshold;
         order.add_history_entry(
-            OrderState::Completed,
-            format!("Dispute resolved: {}", if resolution.len() > 80 { &resolution[..80] } else { &resolution }),
-            current_time,
+            current_state,
+            format!("Panel verifier {} voted ({}/{} votes)", position + 1, votes, threshold),
+            now,
         );
         
+        emit!(VerifierVoted {
+            version: EVENT_VERSION,
+            order: order.key(),
+            verifier: signer,
+            vote,
+            votes,
+            threshold,
+            timestamp: now,
+        });
+        
         Ok(())
     }
 
-    pub fn partial_release_funds(ctx: Context<PartialReleaseFunds>, amount: u64) -> Result<()> {
+    pub fn partial_release_funds<'info>(ctx: Context<'_, '_, '_, 'info, PartialReleaseFunds<'info>>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
         let order = &mut ctx.accounts.order;
         require!(order.state == OrderState::Delivered || order.state == OrderState::InTransit, EscrowError::InvalidState);
+        require!(order.milestones.is_empty(), EscrowError::MilestoneScheduleActive);
         let remaining = order.amount - order.released_amount - order.refunded_amount;
         require!(amount > 0 && amount <= remaining, EscrowError::InvalidPartialAmount);
-        // Only verifier or importer can release
+        // Only verifier or importer can release; panel orders release through votes instead
         let signer = ctx.accounts.signer.key;
-        require!(signer == &order.verifier || signer == &order.importer, EscrowError::Unauthorized);
-        // Transfer funds
-        match order.token_mint {
-            None => {
-                let ix = system_instruction::transfer(
-                    ctx.accounts.escrow_pda.key,
-                    ctx.accounts.exporter.key,
-                    amount,
-                );
-                let order_key = order.key();
-                let seeds = &[
-                    b"escrow_pda",
-                    order_key.as_ref(),
-                    &[ctx.bumps.escrow_pda],
-                ];
-                let signer = &[&seeds[..]];
-                invoke_signed(
-                    &ix,
-                    &[
-                        ctx.accounts.escrow_pda.to_account_info(),
-                        ctx.accounts.exporter.to_account_info(),
-                        ctx.accounts.system_program.to_account_info(),
-                    ],
-                    signer,
-                )?;
-            },
-            Some(_mint) => {
-                let cpi_accounts = token::Transfer {
-                    from: ctx.accounts.escrow_token_account.as_ref().unwrap().to_account_info(),
-                    to: ctx.accounts.exporter_token_account.as_ref().unwrap().to_account_info(),
-                    authority: ctx.accounts.escrow_pda.to_account_info(),
-                };
-                let order_key = order.key();
-                let seeds = &[
-                    b"escrow_pda",
-                    order_key.as_ref(),
-                    &[ctx.bumps.escrow_pda],
-                ];
-                let signer = &[&seeds[..]];
-                let cpi_ctx = CpiContext::new_with_signer(
-                    ctx.accounts.token_program.as_ref().unwrap().to_account_info(),
-                    cpi_accounts,
-                    signer,
-                );
-                token::transfer(cpi_ctx, amount)?;
-            }
-        }
+        require!(
+            signer == &order.importer || (order.verifier_panel.is_empty() && signer == &order.verifier),
+            EscrowError::Unauthorized
+        );
+        // Transfer funds, less the protocol fee which goes to the treasury
+        let fee = ctx.accounts.config.protocol_fee(amount, order.min_fee);
+        let vault = EscrowVault::escrow(
+            order,
+            &ctx.accounts.escrow_pda,
+            ctx.accounts.escrow_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        vault.pay(
+            &ctx.accounts.exporter,
+            ctx.accounts.exporter_token_account.as_ref(),
+            amount - fee,
+        )?;
+        vault.pay(
+            &ctx.accounts.treasury,
+            ctx.accounts.treasury_token_account.as_ref(),
+            fee,
+        )?;
         order.released_amount += amount;
         let now = Clock::get()?.unix_timestamp;
         let current_state = order.state.clone();
         order.add_history_entry(
             current_state,
-            format!("Partial release: {} units to exporter", amount),
+            format!("Partial release: {} units to exporter", amount - fee),
             now,
         );
+        order.record_fee(fee, now);
+        emit!(FundsReleased {
+            version: EVENT_VERSION,
+            order: order.key(),
+            amount: amount - fee,
+            fee,
+            milestone: None,
+            total_released: order.released_amount,
+            timestamp: now,
+        });
         // If fully released, mark as completed
         if order.released_amount + order.refunded_amount == order.amount {
             order.state = OrderState::Completed;
@@ -635,66 +1473,49 @@ pub mod escrow {
         Ok(())
     }
 
-    pub fn partial_refund(ctx: Context<PartialRefund>, amount: u64, current_time: i64) -> Result<()> {
+    pub fn partial_refund<'info>(ctx: Context<'_, '_, '_, 'info, PartialRefund<'info>>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let now = Clock::get()?.unix_timestamp;
         let order = &mut ctx.accounts.order;
-        require!(order.state != OrderState::Completed, EscrowError::InvalidState);
+        require!(
+            order.state != OrderState::Completed && order.state != OrderState::Disputed,
+            EscrowError::InvalidState
+        );
         require!(order.deadline_approved, EscrowError::DeadlineNotApproved);
-        require!(current_time > order.approved_deadline, EscrowError::TooEarlyForRefund);
+        require!(now > order.final_deadline()?, EscrowError::TooEarlyForRefund);
+        require!(order.milestones.is_empty(), EscrowError::MilestoneScheduleActive);
         let remaining = order.amount - order.released_amount - order.refunded_amount;
         require!(amount > 0 && amount <= remaining, EscrowError::InvalidPartialAmount);
         // Only importer can refund
         require!(order.importer == *ctx.accounts.importer.key, EscrowError::Unauthorized);
         // Transfer funds
-        match order.token_mint {
-            None => {
-                let ix = system_instruction::transfer(
-                    ctx.accounts.escrow_pda.key,
-                    ctx.accounts.importer.key,
-                    amount,
-                );
-                let order_key = order.key();
-                let seeds = &[
-                    b"escrow_pda",
-                    order_key.as_ref(),
-                    &[ctx.bumps.escrow_pda],
-                ];
-                let signer = &[&seeds[..]];
-                invoke_signed(
-                    &ix,
-                    &[
-                        ctx.accounts.escrow_pda.to_account_info(),
-                        ctx.accounts.importer.to_account_info(),
-                        ctx.accounts.system_program.to_account_info(),
-                    ],
-                    signer,
-                )?;
-            },
-            Some(_mint) => {
-                let cpi_accounts = token::Transfer {
-                    from: ctx.accounts.escrow_token_account.as_ref().unwrap().to_account_info(),
-                    to: ctx.accounts.importer_token_account.as_ref().unwrap().to_account_info(),
-                    authority: ctx.accounts.escrow_pda.to_account_info(),
-                };
-                let order_key = order.key();
-                let seeds = &[
-                    b"escrow_pda",
-                    order_key.as_ref(),
-                    &[ctx.bumps.escrow_pda],
-                ];
-                let signer = &[&seeds[..]];
-                let cpi_ctx = CpiContext::new_with_signer(
-                    ctx.accounts.token_program.as_ref().unwrap().to_account_info(),
-                    cpi_accounts,
-                    signer,
-                );
-                token::transfer(cpi_ctx, amount)?;
-            }
-        }
+        let vault = EscrowVault::escrow(
+            order,
+            &ctx.accounts.escrow_pda,
+            ctx.accounts.escrow_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        vault.pay(
+            &ctx.accounts.importer,
+            ctx.accounts.importer_token_account.as_ref(),
+            amount,
+        )?;
         order.refunded_amount += amount;
+        emit!(Refunded {
+            version: EVENT_VERSION,
+            order: order.key(),
+            amount,
+            milestone: None,
+            total_refunded: order.refunded_amount,
+            timestamp: now,
+        });
         order.add_history_entry(
             OrderState::Refunded,
             format!("Partial refund: {} units to importer", amount),
-            current_time,
+            now,
         );
         // If fully refunded/released, mark as refunded/completed
         if order.released_amount + order.refunded_amount == order.amount {
@@ -703,291 +1524,2816 @@ pub mod escrow {
             order.add_history_entry(
                 final_state,
                 "Order completed - all funds released/refunded".to_string(),
-                current_time,
+                now,
             );
         }
         Ok(())
     }
-}
-
-#[derive(Accounts)]
-pub struct CreateSolOrder<'info> {
-    #[account(init, payer = importer, space = Order::LEN)]
-    pub order: Account<'info, Order>,
-    #[account(mut)]
-    pub importer: Signer<'info>,
-    /// CHECK: This is not dangerous because we are only transferring SOL to it.
-    #[account(mut)]
-    pub escrow_pda: AccountInfo<'info>,
-    pub system_program: Program<'info, System>,
-}
 
-#[derive(Accounts)]
-pub struct CreateSplOrder<'info> {
-    #[account(init, payer = importer, space = Order::LEN)]
-    pub order: Account<'info, Order>,
-    #[account(mut)]
-    pub importer: Signer<'info>,
-    #[account(mut)]
-    pub importer_token_account: Account<'info, TokenAccount>,
-    #[account(
-        init,
-        payer = importer,
-        token::mint = token_mint,
-        token::authority = escrow_pda,
-    )]
-    pub escrow_token_account: Account<'info, TokenAccount>,
-    /// CHECK: This is not dangerous because we are only using it as a PDA seed.
-    #[account(mut)]
-    pub escrow_pda: AccountInfo<'info>,
-    pub token_mint: Account<'info, token::Mint>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-}
+    pub fn submit_milestone(ctx: Context<SubmitMilestone>, index: u8) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        require!(order.is_live(), EscrowError::InvalidState);
+        require!(order.exporter == *ctx.accounts.exporter.key, EscrowError::Unauthorized);
+        
+        let now = Clock::get()?.unix_timestamp;
+        let milestone = order.milestones.get_mut(index as usize).ok_or(EscrowError::MilestoneNotFound)?;
+        require!(milestone.state == MilestoneState::Pending, EscrowError::InvalidMilestoneState);
+        require!(now <= milestone.deadline, EscrowError::DeadlinePassed);
+        milestone.state = MilestoneState::Submitted;
+        let label = milestone.label.clone();
+        
+        // Add history entry
+        let current_state = order.state.clone();
+        order.add_history_entry(
+            current_state,
+            format!("Milestone {} submitted by exporter: {}", index, label),
+            now,
+        );
+        
+        emit!(MilestoneSubmitted {
+            version: EVENT_VERSION,
+            order: order.key(),
+            index,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
 
+    pub fn approve_milestone(ctx: Context<ApproveMilestone>, index: u8) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        require!(order.is_live(), EscrowError::InvalidState);
+        
+        let now = Clock::get()?.unix_timestamp;
+        let milestone = order.milestones.get_mut(index as usize).ok_or(EscrowError::MilestoneNotFound)?;
+        require!(milestone.approver == *ctx.accounts.approver.key, EscrowError::Unauthorized);
+        require!(milestone.state == MilestoneState::Submitted, EscrowError::InvalidMilestoneState);
+        milestone.state = MilestoneState::Approved;
+        let label = milestone.label.clone();
+        
+        // Add history entry
+        let current_state = order.state.clone();
+        order.add_history_entry(
+            current_state,
+            format!("Milestone {} approved: {}", index, label),
+            now,
+        );
+        
+        emit!(MilestoneApproved {
+            version: EVENT_VERSION,
+            order: order.key(),
+            index,
+            approver: *ctx.accounts.approver.key,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct ShipGoods<'info> {
-    #[account(mut, has_one = exporter)]
-    pub order: Account<'info, Order>,
-    pub exporter: Signer<'info>,
-}
+    pub fn release_milestone<'info>(ctx: Context<'_, '_, '_, 'info, ReleaseMilestone<'info>>, index: u8) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let order = &mut ctx.accounts.order;
+        require!(order.is_live(), EscrowError::InvalidState);
+        
+        let milestone = order.milestones.get(index as usize).ok_or(EscrowError::MilestoneNotFound)?;
+        require!(milestone.state == MilestoneState::Approved, EscrowError::InvalidMilestoneState);
+        let amount = milestone.amount;
+        
+        // Pay the tranche to exporter, less the protocol fee which goes to the treasury
+        let fee = ctx.accounts.config.protocol_fee(amount, order.min_fee);
+        let vault = EscrowVault::escrow(
+            order,
+            &ctx.accounts.escrow_pda,
+            ctx.accounts.escrow_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        vault.pay(
+            &ctx.accounts.exporter,
+            ctx.accounts.exporter_token_account.as_ref(),
+            amount - fee,
+        )?;
+        vault.pay(
+            &ctx.accounts.treasury,
+            ctx.accounts.treasury_token_account.as_ref(),
+            fee,
+        )?;
+        
+        order.milestones[index as usize].state = MilestoneState::Released;
+        order.released_amount += amount;
+        let now = Clock::get()?.unix_timestamp;
+        let current_state = order.state.clone();
+        order.add_history_entry(
+            current_state,
+            format!("Milestone {} released: {} units to exporter", index, amount - fee),
+            now,
+        );
+        order.record_fee(fee, now);
+        emit!(FundsReleased {
+            version: EVENT_VERSION,
+            order: order.key(),
+            amount: amount - fee,
+            fee,
+            milestone: Some(index),
+            total_released: order.released_amount,
+            timestamp: now,
+        });
+        // If fully released/refunded, mark as completed
+        if order.released_amount + order.refunded_amount == order.amount {
+            order.state = OrderState::Completed;
+            order.add_history_entry(
+                OrderState::Completed,
+                "Order completed - all funds released/refunded".to_string(),
+                now,
+            );
+        }
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct ConfirmDelivery<'info> {
-    #[account(mut)]
-    pub order: Account<'info, Order>,
-    pub signer: Signer<'info>, // Importer or verifier
-    /// CHECK: This is the escrow PDA
-    #[account(
-        mut,
-        seeds = [b"escrow_pda", order.key().as_ref()],
-        bump
-    )]
-    pub escrow_pda: UncheckedAccount<'info>,
-    /// CHECK: Exporter account
-    #[account(mut)]
-    pub exporter: UncheckedAccount<'info>,
-    pub system_program: Program<'info, System>,
-    // SPL token support
-    #[account(mut)]
-    pub escrow_token_account: Option<Account<'info, TokenAccount>>,
-    #[account(mut)]
-    pub exporter_token_account: Option<Account<'info, TokenAccount>>,
-    pub token_program: Option<Program<'info, Token>>,
-}
+    pub fn refund_milestone<'info>(ctx: Context<'_, '_, '_, 'info, RefundMilestone<'info>>, index: u8) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let order = &mut ctx.accounts.order;
+        require!(order.is_live(), EscrowError::InvalidState);
+        
+        let now = Clock::get()?.unix_timestamp;
+        let milestone = order.milestones.get(index as usize).ok_or(EscrowError::MilestoneNotFound)?;
+        // Approved milestones are owed to the exporter even if released late
+        require!(
+            milestone.state == MilestoneState::Pending || milestone.state == MilestoneState::Submitted,
+            EscrowError::InvalidMilestoneState
+        );
+        require!(now > milestone.deadline, EscrowError::TooEarlyForRefund);
+        let amount = milestone.amount;
+        
+        let vault = EscrowVault::escrow(
+            order,
+            &ctx.accounts.escrow_pda,
+            ctx.accounts.escrow_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        vault.pay(
+            &ctx.accounts.importer,
+            ctx.accounts.importer_token_account.as_ref(),
+            amount,
+        )?;
+        
+        order.milestones[index as usize].state = MilestoneState::Refunded;
+        order.refunded_amount += amount;
+        emit!(Refunded {
+            version: EVENT_VERSION,
+            order: order.key(),
+            amount,
+            milestone: Some(index),
+            total_refunded: order.refunded_amount,
+            timestamp: now,
+        });
+        let current_state = order.state.clone();
+        order.add_history_entry(
+            current_state,
+            format!("Milestone {} deadline passed - {} units refunded to importer", index, amount),
+            now,
+        );
+        // If fully refunded/released, mark as refunded/completed
+        if order.released_amount + order.refunded_amount == order.amount {
+            order.state = if order.released_amount > 0 { OrderState::Completed } else { OrderState::Refunded };
+            let final_state = order.state.clone();
+            order.add_history_entry(
+                final_state,
+                "Order completed - all funds released/refunded".to_string(),
+                now,
+            );
+        }
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct CheckDeadlineAndRefund<'info> {
-    #[account(mut)]
-    pub order: Account<'info, Order>,
-    /// CHECK: This is the escrow PDA
+    pub fn amend_order_amount<'info>(
+        ctx: Context<'_, '_, '_, 'info, AmendOrderAmount<'info>>,
+        new_amount: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let now = Clock::get()?.unix_timestamp;
+        let order = &mut ctx.accounts.order;
+        order.check_amendment(new_amount)?;
+        // On an SPL order the new amount must stay within the mint's limits
+        if order.token_mint.is_some() {
+            let allowed_mint = ctx.accounts.allowed_mint.as_ref().ok_or(EscrowError::MintNotAllowed)?;
+            allowed_mint.check_amount(new_amount)?;
+        }
+        
+        // Increases are pulled from the importer, decreases refunded to them
+        let vault = EscrowVault::escrow(
+            order,
+            &ctx.accounts.escrow_pda,
+            ctx.accounts.escrow_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        let old_amount = order.amount;
+        if new_amount > old_amount {
+            order.amount += vault.deposit(
+                &ctx.accounts.importer,
+                ctx.accounts.importer_token_account.as_ref(),
+                new_amount - old_amount,
+            )?;
+        } else {
+            vault.pay(
+                &ctx.accounts.importer,
+                ctx.accounts.importer_token_account.as_ref(),
+                old_amount - new_amount,
+            )?;
+            order.amount = new_amount;
+        }
+        
+        // Add history entry
+        let amount = order.amount;
+        order.add_history_entry(
+            OrderState::PendingShipment,
+            format!("Order amount amended by importer and exporter: {} -> {}", old_amount, amount),
+            now,
+        );
+        
+        emit!(OrderAmountAmended {
+            version: EVENT_VERSION,
+            order: order.key(),
+            old_amount,
+            new_amount: amount,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+
+    pub fn cancel_order<'info>(ctx: Context<'_, '_, '_, 'info, CancelOrder<'info>>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let now = Clock::get()?.unix_timestamp;
+        let order = &mut ctx.accounts.order;
+        require!(order.importer == *ctx.accounts.importer.key, EscrowError::Unauthorized);
+        
+        // The importer may walk away until the deadline is agreed; after that, until shipment,
+        // cancelling needs the exporter's signature as well. An offer awaiting acceptance is
+        // binding on the importer until the acceptance window has expired.
+        let mutual = ctx.accounts.exporter.is_some();
+        order.check_cancellable(mutual, now)?;
+        
+        // Refund everything still in escrow and close the vault
+        let amount = order.amount - order.released_amount - order.refunded_amount;
+        let vault = EscrowVault::escrow(
+            order,
+            &ctx.accounts.escrow_pda,
+            ctx.accounts.escrow_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        vault.pay(
+            &ctx.accounts.importer,
+            ctx.accounts.importer_token_account.as_ref(),
+            amount,
+        )?;
+        vault.close_vault(&ctx.accounts.importer, ctx.accounts.importer_token_account.as_ref())?;
+        
+        order.refunded_amount += amount;
+        order.state = OrderState::Cancelled;
+        
+        // Add history entry
+        order.add_history_entry(
+            OrderState::Cancelled,
+            if mutual {
+                "Order cancelled by importer and exporter - funds refunded".to_string()
+            } else {
+                "Order cancelled by importer - funds refunded".to_string()
+            },
+            now,
+        );
+        
+        emit!(OrderCancelled {
+            version: EVENT_VERSION,
+            order: order.key(),
+            mutual,
+            refunded_amount: amount,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+
+    pub fn close_order<'info>(ctx: Context<'_, '_, '_, 'info, CloseOrder<'info>>) -> Result<()> {
+        let order = &ctx.accounts.order;
+        require!(order.importer == *ctx.accounts.importer.key, EscrowError::Unauthorized);
+        order.check_closable()?;
+        let now = Clock::get()?.unix_timestamp;
+        
+        // Cancelled orders already closed their vault
+        if order.state != OrderState::Cancelled {
+            let vault = EscrowVault::escrow(
+                order,
+                &ctx.accounts.escrow_pda,
+                ctx.accounts.escrow_token_account.as_ref(),
+                ctx.accounts.token_program.as_ref(),
+                ctx.accounts.token_mint.as_ref(),
+                ctx.remaining_accounts,
+                &ctx.accounts.system_program,
+            );
+            vault.close_vault(&ctx.accounts.importer, ctx.accounts.importer_token_account.as_ref())?;
+        }
+        
+        // Optionally keep a compact audit record of how the order settled
+        let archived = ctx.accounts.settlement.is_some();
+        if let Some(settlement) = ctx.accounts.settlement.as_mut() {
+            settlement.order = order.key();
+            settlement.order_id = order.order_id;
+            settlement.importer = order.importer;
+            settlement.exporter = order.exporter;
+            settlement.token_mint = order.token_mint;
+            settlement.amount = order.amount;
+            settlement.released_amount = order.released_amount;
+            settlement.refunded_amount = order.refunded_amount;
+            settlement.fee_amount = order.fee_amount;
+            settlement.final_state = order.state.clone();
+            settlement.closed_at = now;
+            settlement.bump = ctx.bumps.settlement.unwrap_or_default();
+        }
+        
+        emit!(OrderClosed {
+            version: EVENT_VERSION,
+            order: order.key(),
+            archived,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+
+    pub fn post_bond<'info>(ctx: Context<'_, '_, '_, 'info, PostBond<'info>>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let order = &mut ctx.accounts.order;
+        require!(order.state == OrderState::PendingDeadlineApproval, EscrowError::InvalidState);
+        require!(order.exporter == *ctx.accounts.exporter.key, EscrowError::Unauthorized);
+        require!(order.bond_amount > 0 && !order.bond_posted, EscrowError::InvalidBond);
+        // SOL bonds are posted with post_sol_bond
+        require!(order.token_mint.is_some(), EscrowError::MissingSPLAccount);
+        
+        // The bond held is what the vault received, net of any Token-2022 transfer fee
+        order.bond_bump = ctx.bumps.bond_pda;
+        let bond_vault = EscrowVault::bond(
+            order,
+            &ctx.accounts.bond_pda,
+            ctx.accounts.bond_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        let amount = bond_vault.deposit(
+            &ctx.accounts.exporter,
+            ctx.accounts.exporter_token_account.as_ref(),
+            order.bond_amount,
+        )?;
+        order.bond_amount = amount;
+        order.bond_posted = true;
+        order.bond_vault_bump = ctx.bumps.bond_token_account.unwrap_or_default();
+        
+        // Add history entry
+        let now = Clock::get()?.unix_timestamp;
+        order.add_history_entry(
+            OrderState::PendingDeadlineApproval,
+            format!("Exporter posted bond of {} units", amount),
+            now,
+        );
+        
+        emit!(BondPosted {
+            version: EVENT_VERSION,
+            order: order.key(),
+            amount,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+
+    pub fn post_sol_bond(ctx: Context<PostSolBond>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let order = &mut ctx.accounts.order;
+        require!(order.state == OrderState::PendingDeadlineApproval, EscrowError::InvalidState);
+        require!(order.exporter == *ctx.accounts.exporter.key, EscrowError::Unauthorized);
+        require!(order.bond_amount > 0 && !order.bond_posted, EscrowError::InvalidBond);
+        require!(order.token_mint.is_none(), EscrowError::InvalidTokenAccount);
+        
+        // Transfer SOL into the program-owned bond vault, on top of its rent reserve
+        ctx.accounts.bond_pda.order = order.key();
+        let bond_pda = ctx.accounts.bond_pda.to_account_info();
+        order.bond_bump = ctx.bumps.bond_pda;
+        let bond_vault = EscrowVault::bond(
+            order,
+            &bond_pda,
+            None,
+            None,
+            None,
+            &[],
+            &ctx.accounts.system_program,
+        );
+        let amount = bond_vault.deposit(&ctx.accounts.exporter, None, order.bond_amount)?;
+        order.bond_posted = true;
+        order.bond_vault_bump = 0; // No token vault for SOL bonds
+        
+        // Add history entry
+        let now = Clock::get()?.unix_timestamp;
+        order.add_history_entry(
+            OrderState::PendingDeadlineApproval,
+            format!("Exporter posted bond of {} lamports", amount),
+            now,
+        );
+        
+        emit!(BondPosted {
+            version: EVENT_VERSION,
+            order: order.key(),
+            amount,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+
+    pub fn settle_bond<'info>(ctx: Context<'_, '_, '_, 'info, SettleBond<'info>>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let order = &mut ctx.accounts.order;
+        require!(order.bond_posted, EscrowError::InvalidBond);
+        
+        // Orders that end without the exporter at fault get the bond back; refunded orders
+        // mean a missed deadline (or a verdict for the importer), so the bond is slashed
+        let slash = match order.state {
+            OrderState::Completed | OrderState::Cancelled => false,
+            OrderState::Refunded => true,
+            _ => return err!(EscrowError::InvalidState),
+        };
+        let (to_exporter, to_importer) = order.bond_split(slash);
+        let now = Clock::get()?.unix_timestamp;
+        
+        let bond_vault = EscrowVault::bond(
+            order,
+            &ctx.accounts.bond_pda,
+            ctx.accounts.bond_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+            ctx.accounts.token_mint.as_ref(),
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program,
+        );
+        bond_vault.pay(
+            &ctx.accounts.importer,
+            ctx.accounts.importer_token_account.as_ref(),
+            to_importer,
+        )?;
+        bond_vault.pay(
+            &ctx.accounts.exporter,
+            ctx.accounts.exporter_token_account.as_ref(),
+            to_exporter,
+        )?;
+        bond_vault.close_vault(
+            &ctx.accounts.exporter,
+            ctx.accounts.exporter_token_account.as_ref(),
+        )?;
+        order.settle_bond(to_exporter, to_importer, now);
+        
+        emit!(BondSettled {
+            version: EVENT_VERSION,
+            order: order.key(),
+            returned_to_exporter: to_exporter,
+            slashed_to_importer: to_importer,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+
+    pub fn accept_order(ctx: Context<AcceptOrder>, amount: u64, proposed_deadline: i64) -> Result<()> {
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+        let now = Clock::get()?.unix_timestamp;
+        let order = &mut ctx.accounts.order;
+        require!(order.state == OrderState::PendingAcceptance, EscrowError::InvalidState);
+        require!(now <= order.acceptance_deadline, EscrowError::AcceptanceExpired);
+        
+        // The signer acknowledges the amount and deadline it has seen, so the terms
+        // cannot change underneath it
+        require!(
+            amount == order.amount && proposed_deadline == order.proposed_deadline,
+            EscrowError::TermsMismatch
+        );
+        
+        let signer = ctx.accounts.signer.key();
+        let role = if signer == order.exporter {
+            require!(!order.exporter_accepted, EscrowError::AlreadyAccepted);
+            order.exporter_accepted = true;
+            "Exporter"
+        } else if signer == order.verifier && order.require_verifier_acceptance {
+            require!(!order.verifier_accepted, EscrowError::AlreadyAccepted);
+            order.verifier_accepted = true;
+            "Verifier"
+        } else {
+            return err!(EscrowError::Unauthorized);
+        };
+        
+        // The order goes live once every required party has accepted
+        let accepted = order.exporter_accepted
+            && (order.verifier_accepted || !order.require_verifier_acceptance);
+        if accepted {
+            order.state = OrderState::PendingDeadlineApproval;
+        }
+        
+        // Add history entry
+        let current_state = order.state.clone();
+        order.add_history_entry(
+            current_state,
+            if accepted {
+                format!("{} accepted order - waiting for deadline approval", role)
+            } else {
+                format!("{} accepted order", role)
+            },
+            now,
+        );
+        
+        emit!(OrderAccepted {
+            version: EVENT_VERSION,
+            order: order.key(),
+            signer,
+            accepted,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
     #[account(
-        mut,
-        seeds = [b"escrow_pda", order.key().as_ref()],
+        init,
+        payer = admin,
+        space = Config::LEN,
+        seeds = [b"config"],
         bump
     )]
-    pub escrow_pda: UncheckedAccount<'info>,
-    /// CHECK: Importer account
+    pub config: Account<'info, Config>,
     #[account(mut)]
-    pub importer: UncheckedAccount<'info>,
+    pub admin: Signer<'info>,
+    // Only the program's upgrade authority may create the config
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Escrow>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ EscrowError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
     pub system_program: Program<'info, System>,
-    // SPL token support
-    #[account(mut)]
-    pub escrow_token_account: Option<Account<'info, TokenAccount>>,
-    #[account(mut)]
-    pub importer_token_account: Option<Account<'info, TokenAccount>>,
-    pub token_program: Option<Program<'info, Token>>,
-}
-
-#[derive(Accounts)]
-pub struct ApproveDeadline<'info> {
-    #[account(mut)]
-    pub order: Account<'info, Order>,
-    #[account(mut)]
-    pub importer: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ProposeNewDeadline<'info> {
-    #[account(mut)]
-    pub order: Account<'info, Order>,
-    #[account(mut)]
-    pub exporter: Signer<'info>,
+pub struct UpdateConfig<'info> {
+    #[account(mut, seeds = [b"config"], bump = config.bump, has_one = admin @ EscrowError::Unauthorized)]
+    pub config: Account<'info, Config>,
+    pub admin: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct RequestDeadlineExtension<'info> {
-    #[account(mut)]
-    pub order: Account<'info, Order>,
+pub struct AddAllowedMint<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = admin @ EscrowError::Unauthorized)]
+    pub config: Account<'info, Config>,
     #[account(mut)]
-    pub exporter: Signer<'info>,
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = AllowedMint::LEN,
+        seeds = [b"allowed_mint", token_mint.key().as_ref()],
+        bump
+    )]
+    pub allowed_mint: Account<'info, AllowedMint>,
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ApproveDeadlineExtension<'info> {
-    #[account(mut)]
-    pub order: Account<'info, Order>,
-    #[account(mut)]
-    pub importer: Signer<'info>,
+pub struct UpdateAllowedMint<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = admin @ EscrowError::Unauthorized)]
+    pub config: Account<'info, Config>,
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"allowed_mint", allowed_mint.mint.as_ref()],
+        bump = allowed_mint.bump
+    )]
+    pub allowed_mint: Account<'info, AllowedMint>,
 }
 
 #[derive(Accounts)]
-pub struct RejectDeadlineExtension<'info> {
-    #[account(mut)]
-    pub order: Account<'info, Order>,
+pub struct RemoveAllowedMint<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = admin @ EscrowError::Unauthorized)]
+    pub config: Account<'info, Config>,
     #[account(mut)]
-    pub importer: Signer<'info>,
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"allowed_mint", allowed_mint.mint.as_ref()],
+        bump = allowed_mint.bump,
+        close = admin
+    )]
+    pub allowed_mint: Account<'info, AllowedMint>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateOrderMetadata<'info> {
+pub struct RegisterVerifier<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = Verifier::LEN,
+        seeds = [b"verifier", authority.key().as_ref()],
+        bump
+    )]
+    pub verifier_account: Account<'info, Verifier>,
     #[account(mut)]
-    pub order: Account<'info, Order>,
-    pub signer: Signer<'info>,
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct BulkCheckDeadlines<'info> {
-    #[account(mut)]
-    pub order: Account<'info, Order>,
-    /// CHECK: This is the escrow PDA
+pub struct UpdateVerifier<'info> {
     #[account(
         mut,
-        seeds = [b"escrow_pda", order.key().as_ref()],
-        bump
+        seeds = [b"verifier", authority.key().as_ref()],
+        bump = verifier_account.bump,
+        has_one = authority @ EscrowError::Unauthorized
     )]
-    pub escrow_pda: UncheckedAccount<'info>,
-    /// CHECK: Importer account
+    pub verifier_account: Account<'info, Verifier>,
     #[account(mut)]
-    pub importer: UncheckedAccount<'info>,
+    pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct DisputeOrder<'info> {
+pub struct UnbondVerifier<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [b"verifier", authority.key().as_ref()],
+        bump = verifier_account.bump,
+        has_one = authority @ EscrowError::Unauthorized
+    )]
+    pub verifier_account: Account<'info, Verifier>,
     #[account(mut)]
-    pub order: Account<'info, Order>,
-    pub signer: Signer<'info>,
+    pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ResolveDispute<'info> {
-    #[account(mut)]
-    pub order: Account<'info, Order>,
-    pub verifier: Signer<'info>,
+pub struct SlashVerifier<'info> {
+    // Governance over the registry is the config admin
+    #[account(seeds = [b"config"], bump = config.bump, has_one = admin @ EscrowError::Unauthorized)]
+    pub config: Account<'info, Config>,
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"verifier", verifier_account.authority.as_ref()],
+        bump = verifier_account.bump
+    )]
+    pub verifier_account: Account<'info, Verifier>,
+    /// CHECK: Protocol treasury, receives slashed stake
+    #[account(mut, address = config.treasury @ EscrowError::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct PartialReleaseFunds<'info> {
-    #[account(mut)]
+#[instruction(order_id: [u8; 32], exporter: Pubkey, verifier: Pubkey)]
+pub struct CreateSolOrder<'info> {
+    // One order per importer and business reference (e.g. purchase-order number)
+    #[account(
+        init,
+        payer = importer,
+        space = Order::LEN,
+        seeds = [b"order", importer.key().as_ref(), order_id.as_ref()],
+        bump
+    )]
     pub order: Account<'info, Order>,
-    pub signer: Signer<'info>, // Importer or verifier
-    /// CHECK: This is the escrow PDA
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    // Registry entry of the chosen verifier, required when the config demands registered verifiers
+    #[account(seeds = [b"verifier", verifier.as_ref()], bump = verifier_account.bump)]
+    pub verifier_account: Option<Account<'info, Verifier>>,
+    #[account(mut)]
+    pub importer: Signer<'info>,
+    /// CHECK: Settlement record of an earlier order with this reference; must not exist
     #[account(
-        mut,
+        seeds = [b"settlement", importer.key().as_ref(), order_id.as_ref()],
+        bump,
+        constraint = settlement.data_is_empty() @ EscrowError::OrderIdSettled
+    )]
+    pub settlement: UncheckedAccount<'info>,
+    // Program-owned lamport vault; its rent reserve is paid on top of the escrowed amount
+    #[account(
+        init,
+        payer = importer,
+        space = SolVault::LEN,
         seeds = [b"escrow_pda", order.key().as_ref()],
         bump
     )]
-    pub escrow_pda: UncheckedAccount<'info>,
-    /// CHECK: Exporter account
-    #[account(mut)]
-    pub exporter: UncheckedAccount<'info>,
+    pub escrow_pda: Account<'info, SolVault>,
     pub system_program: Program<'info, System>,
-    // SPL token support
-    #[account(mut)]
-    pub escrow_token_account: Option<Account<'info, TokenAccount>>,
-    #[account(mut)]
-    pub exporter_token_account: Option<Account<'info, TokenAccount>>,
-    pub token_program: Option<Program<'info, Token>>,
 }
 
 #[derive(Accounts)]
-pub struct PartialRefund<'info> {
-    #[account(mut)]
-    pub order: Account<'info, Order>,
-    /// CHECK: This is the escrow PDA
+#[instruction(order_id: [u8; 32], exporter: Pubkey, verifier: Pubkey)]
+pub struct CreateSplOrder<'info> {
+    // One order per importer and business reference (e.g. purchase-order number)
     #[account(
-        mut,
-        seeds = [b"escrow_pda", order.key().as_ref()],
+        init,
+        payer = importer,
+        space = Order::LEN,
+        seeds = [b"order", importer.key().as_ref(), order_id.as_ref()],
         bump
     )]
-    pub escrow_pda: UncheckedAccount<'info>,
-    /// CHECK: Importer account
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    // Registry entry of the chosen verifier, required when the config demands registered verifiers
+    #[account(seeds = [b"verifier", verifier.as_ref()], bump = verifier_account.bump)]
+    pub verifier_account: Option<Account<'info, Verifier>>,
     #[account(mut)]
     pub importer: Signer<'info>,
-    pub system_program: Program<'info, System>,
-    // SPL token support
-    #[account(mut)]
-    pub escrow_token_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Settlement record of an earlier order with this reference; must not exist
+    #[account(
+        seeds = [b"settlement", importer.key().as_ref(), order_id.as_ref()],
+        bump,
+        constraint = settlement.data_is_empty() @ EscrowError::OrderIdSettled
+    )]
+    pub settlement: UncheckedAccount<'info>,
+    // Omit for a wrapped-SOL order to fund the vault from the importer's lamports
     #[account(mut)]
-    pub importer_token_account: Option<Account<'info, TokenAccount>>,
-    pub token_program: Option<Program<'info, Token>>,
-}
-
-#[account]
-pub struct Order {
-    pub importer: Pubkey,
-    pub exporter: Pubkey,
-    pub verifier: Pubkey,
-    pub amount: u64,
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    // Vault owned by the escrow PDA, at an address derived from the order
+    #[account(
+        init,
+        payer = importer,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = escrow_pda,
+        token::token_program = token_program,
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: This is not dangerous because we are only using it as a PDA seed.
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump
+    )]
+    pub escrow_pda: AccountInfo<'info>,
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    // Only admin-approved mints can be escrowed
+    #[account(
+        seeds = [b"allowed_mint", token_mint.key().as_ref()],
+        bump = allowed_mint.bump,
+        constraint = allowed_mint.enabled @ EscrowError::MintNotAllowed
+    )]
+    pub allowed_mint: Account<'info, AllowedMint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(Accounts)]
+pub struct ShipGoods<'info> {
+    #[account(mut, has_one = exporter)]
+    pub order: Account<'info, Order>,
+    pub exporter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfirmDelivery<'info> {
+    #[account(mut, has_one = exporter)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub signer: Signer<'info>, // Importer or verifier
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    /// CHECK: Exporter account
+    #[account(mut)]
+    pub exporter: UncheckedAccount<'info>,
+    /// CHECK: Importer account, receives any late-delivery penalty
+    #[account(mut, address = order.importer @ EscrowError::Unauthorized)]
+    pub importer: Option<UncheckedAccount<'info>>,
+    /// CHECK: Protocol fee treasury, receives the fee for SOL orders
+    #[account(mut, address = config.treasury @ EscrowError::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = exporter_token_account.owner == order.exporter @ EscrowError::InvalidTokenAccount,
+        constraint = Some(exporter_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub exporter_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == config.treasury @ EscrowError::InvalidTreasury,
+        constraint = Some(treasury_token_account.mint) == order.token_mint @ EscrowError::InvalidTreasury
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: Exporter bond PDA, required once a bond is posted
+    #[account(
+        mut,
+        seeds = [b"bond_pda", order.key().as_ref()],
+        bump = order.bond_bump
+    )]
+    pub bond_pda: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        seeds = [b"bond_vault", order.key().as_ref()],
+        bump = order.bond_vault_bump
+    )]
+    pub bond_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    // Writable so withheld Token-2022 transfer fees can be harvested before the vault closes
+    #[account(mut, constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct CheckDeadlineAndRefund<'info> {
+    #[account(mut, has_one = importer)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    /// CHECK: Importer account
+    #[account(mut)]
+    pub importer: UncheckedAccount<'info>,
+    /// CHECK: Exporter account, receives the unslashed part of a posted bond
+    #[account(mut, address = order.exporter @ EscrowError::Unauthorized)]
+    pub exporter: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = exporter_token_account.owner == order.exporter @ EscrowError::InvalidTokenAccount,
+        constraint = Some(exporter_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub exporter_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: Exporter bond PDA, required once a bond is posted
+    #[account(
+        mut,
+        seeds = [b"bond_pda", order.key().as_ref()],
+        bump = order.bond_bump
+    )]
+    pub bond_pda: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        seeds = [b"bond_vault", order.key().as_ref()],
+        bump = order.bond_vault_bump
+    )]
+    pub bond_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    // Writable so withheld Token-2022 transfer fees can be harvested before the vault closes
+    #[account(mut, constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveDeadline<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// CHECK: Importer account, funds or receives any agreed change in amount
+    #[account(mut, address = order.importer @ EscrowError::Unauthorized)]
+    pub importer: UncheckedAccount<'info>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeNewDeadline<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support, needed when the importer offers a higher amount
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    // SPL orders only: the mint's allowlist entry, for its order limits
+    #[account(
+        seeds = [b"allowed_mint", allowed_mint.mint.as_ref()],
+        bump = allowed_mint.bump,
+        constraint = Some(allowed_mint.mint) == order.token_mint @ EscrowError::MintNotAllowed
+    )]
+    pub allowed_mint: Option<Account<'info, AllowedMint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct RequestDeadlineExtension<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub exporter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveDeadlineExtension<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub importer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptExtensionCounterOffer<'info> {
+    #[account(mut, has_one = exporter, has_one = importer)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub exporter: Signer<'info>,
+    /// CHECK: Importer account, receives the agreed price reduction
+    #[account(mut)]
+    pub importer: UncheckedAccount<'info>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct RejectDeadlineExtension<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    #[account(mut)]
+    pub importer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireDeadlineExtension<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateOrderMetadata<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct BulkCheckDeadlines<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    /// CHECK: Importer account
+    #[account(mut)]
+    pub importer: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DisputeOrder<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitVerifierVote<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub verifier: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(mut, has_one = exporter, has_one = importer)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub verifier: Signer<'info>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    /// CHECK: Exporter account
+    #[account(mut)]
+    pub exporter: UncheckedAccount<'info>,
+    /// CHECK: Importer account
+    #[account(mut)]
+    pub importer: UncheckedAccount<'info>,
+    /// CHECK: Protocol fee treasury, receives the fee for SOL orders
+    #[account(mut, address = config.treasury @ EscrowError::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = exporter_token_account.owner == order.exporter @ EscrowError::InvalidTokenAccount,
+        constraint = Some(exporter_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub exporter_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == config.treasury @ EscrowError::InvalidTreasury,
+        constraint = Some(treasury_token_account.mint) == order.token_mint @ EscrowError::InvalidTreasury
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct PartialReleaseFunds<'info> {
+    #[account(mut, has_one = exporter)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub signer: Signer<'info>, // Importer or verifier
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    /// CHECK: Exporter account
+    #[account(mut)]
+    pub exporter: UncheckedAccount<'info>,
+    /// CHECK: Protocol fee treasury, receives the fee for SOL orders
+    #[account(mut, address = config.treasury @ EscrowError::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = exporter_token_account.owner == order.exporter @ EscrowError::InvalidTokenAccount,
+        constraint = Some(exporter_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub exporter_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == config.treasury @ EscrowError::InvalidTreasury,
+        constraint = Some(treasury_token_account.mint) == order.token_mint @ EscrowError::InvalidTreasury
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct PartialRefund<'info> {
+    #[account(mut, has_one = importer)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    /// CHECK: Importer account
+    #[account(mut)]
+    pub importer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitMilestone<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    pub exporter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveMilestone<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    pub approver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseMilestone<'info> {
+    #[account(mut, has_one = exporter)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    /// CHECK: Exporter account
+    #[account(mut)]
+    pub exporter: UncheckedAccount<'info>,
+    /// CHECK: Protocol fee treasury, receives the fee for SOL orders
+    #[account(mut, address = config.treasury @ EscrowError::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = exporter_token_account.owner == order.exporter @ EscrowError::InvalidTokenAccount,
+        constraint = Some(exporter_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub exporter_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == config.treasury @ EscrowError::InvalidTreasury,
+        constraint = Some(treasury_token_account.mint) == order.token_mint @ EscrowError::InvalidTreasury
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct RefundMilestone<'info> {
+    #[account(mut, has_one = importer)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    /// CHECK: Importer account
+    #[account(mut)]
+    pub importer: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct AmendOrderAmount<'info> {
+    #[account(mut, has_one = importer, has_one = exporter)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub importer: Signer<'info>,
+    pub exporter: Signer<'info>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    // SPL orders only: the mint's allowlist entry, for its order limits
+    #[account(
+        seeds = [b"allowed_mint", allowed_mint.mint.as_ref()],
+        bump = allowed_mint.bump,
+        constraint = Some(allowed_mint.mint) == order.token_mint @ EscrowError::MintNotAllowed
+    )]
+    pub allowed_mint: Option<Account<'info, AllowedMint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(mut, has_one = importer)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub importer: Signer<'info>,
+    // Required to cancel once the deadline has been approved, or before the acceptance window expires
+    #[account(address = order.exporter @ EscrowError::Unauthorized)]
+    pub exporter: Option<Signer<'info>>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    // Writable so withheld Token-2022 transfer fees can be harvested before the vault closes
+    #[account(mut, constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct CloseOrder<'info> {
+    // Rent goes back to the importer, who paid for the order
+    #[account(mut, has_one = importer, close = importer)]
+    pub order: Account<'info, Order>,
+    #[account(mut)]
+    pub importer: Signer<'info>,
+    /// CHECK: This is the escrow PDA
+    #[account(
+        mut,
+        seeds = [b"escrow_pda", order.key().as_ref()],
+        bump = order.escrow_bump
+    )]
+    pub escrow_pda: UncheckedAccount<'info>,
+    // Pass to archive a settlement record for audit. It is keyed by the business reference,
+    // which then cannot be used for a new order by this importer; closing without a record
+    // frees the reference for reuse
+    #[account(
+        init,
+        payer = importer,
+        space = SettlementRecord::LEN,
+        seeds = [b"settlement", importer.key().as_ref(), order.order_id.as_ref()],
+        bump
+    )]
+    pub settlement: Option<Account<'info, SettlementRecord>>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", order.key().as_ref()],
+        bump = order.vault_bump
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    // Receives any tokens still in the vault, such as a balance donated after settlement
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    // Writable so withheld Token-2022 transfer fees can be harvested before the vault closes
+    #[account(mut, constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct PostBond<'info> {
+    #[account(mut, has_one = exporter)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub exporter: Signer<'info>,
+    /// CHECK: Exporter bond PDA, signs for the SPL bond vault
+    #[account(
+        mut,
+        seeds = [b"bond_pda", order.key().as_ref()],
+        bump
+    )]
+    pub bond_pda: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        constraint = exporter_token_account.owner == order.exporter @ EscrowError::InvalidTokenAccount,
+        constraint = Some(exporter_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub exporter_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = exporter,
+        seeds = [b"bond_vault", order.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = bond_pda,
+        token::token_program = token_program,
+    )]
+    pub bond_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct PostSolBond<'info> {
+    #[account(mut, has_one = exporter)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub exporter: Signer<'info>,
+    // Program-owned lamport vault for the bond; its rent reserve is paid on top of the bond
+    #[account(
+        init,
+        payer = exporter,
+        space = SolVault::LEN,
+        seeds = [b"bond_pda", order.key().as_ref()],
+        bump
+    )]
+    pub bond_pda: Account<'info, SolVault>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleBond<'info> {
+    #[account(mut, has_one = exporter, has_one = importer)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    /// CHECK: Exporter bond PDA
+    #[account(
+        mut,
+        seeds = [b"bond_pda", order.key().as_ref()],
+        bump = order.bond_bump
+    )]
+    pub bond_pda: UncheckedAccount<'info>,
+    /// CHECK: Exporter account
+    #[account(mut)]
+    pub exporter: UncheckedAccount<'info>,
+    /// CHECK: Importer account
+    #[account(mut)]
+    pub importer: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // SPL token support
+    #[account(
+        mut,
+        seeds = [b"bond_vault", order.key().as_ref()],
+        bump = order.bond_vault_bump
+    )]
+    pub bond_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = exporter_token_account.owner == order.exporter @ EscrowError::InvalidTokenAccount,
+        constraint = Some(exporter_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub exporter_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = importer_token_account.owner == order.importer @ EscrowError::InvalidTokenAccount,
+        constraint = Some(importer_token_account.mint) == order.token_mint @ EscrowError::InvalidTokenAccount
+    )]
+    pub importer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    // Writable so withheld Token-2022 transfer fees can be harvested before the vault closes
+    #[account(mut, constraint = Some(token_mint.key()) == order.token_mint @ EscrowError::InvalidTokenAccount)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOrder<'info> {
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub signer: Signer<'info>,
+}
+
+#[account]
+pub struct Order {
+    pub order_id: [u8; 32], // Client business reference, part of the order PDA seeds
+    pub bump: u8,
+    pub importer: Pubkey,
+    pub exporter: Pubkey,
+    pub verifier: Pubkey,
+    pub amount: u64,
     pub released_amount: u64, // Total released to exporter
     pub refunded_amount: u64, // Total refunded to importer
+    pub fee_amount: u64, // Total protocol fees taken out of releases
     pub state: OrderState,
     pub created_at: i64,
-    pub proposed_deadline: i64,  // Deadline proposed by exporter
+    pub proposed_deadline: i64,  // Deadline of the latest proposal (timestamp or duration, per deadline_mode)
     pub approved_deadline: i64,  // Final deadline after importer approval
     pub deadline_approved: bool, // Whether importer has approved the deadline
     pub extension_requested: bool, // Whether an extension has been requested
     pub extension_deadline: i64, // Proposed extension deadline
     pub bill_of_lading_hash: [u8; 32],
-    pub token_mint: Option<Pubkey>, // None = SOL, Some = SPL token
-    pub history: Vec<OrderHistoryEntry>, // Order state history
-    pub metadata: OrderMetadata, // Search/filter metadata
-    pub last_updated: i64, // Last modification timestamp
+    pub token_mint: Option<Pubkey>, // None = SOL, Some = SPL token
+    pub escrow_bump: u8, // Bump of the ["escrow_pda", order] signer
+    pub vault_bump: u8, // Bump of the ["escrow_vault", order] token vault (SPL only)
+    pub history: Vec<OrderHistoryEntry>, // Order state history
+    pub metadata: OrderMetadata, // Search/filter metadata
+    pub last_updated: i64, // Last modification timestamp
+    pub milestones: Vec<Milestone>, // Optional payment schedule, empty = single release
+    pub bond_amount: u64, // Exporter performance bond required before deadline approval
+    pub bond_slash_bps: u16, // Share of the bond paid to importer on a missed deadline
+    pub bond_posted: bool, // Whether the bond is currently held in the bond vault
+    pub bond_bump: u8, // Bump of the ["bond_pda", order] signer
+    pub bond_vault_bump: u8, // Bump of the ["bond_vault", order] token vault (SPL only)
+    pub grace_period: i64, // Seconds after approved_deadline in which delivery is late but accepted
+    pub late_penalty_bps_per_day: u16, // Liquidated damages per (started) day late
+    pub acceptance_deadline: i64, // Importer may reclaim funds after this if the order was not accepted
+    pub require_verifier_acceptance: bool,
+    pub exporter_accepted: bool,
+    pub verifier_accepted: bool,
+    pub proposals: Vec<DeadlineProposal>, // Negotiation rounds, latest last
+    pub deadline_mode: DeadlineMode,
+    pub max_extensions: u8,
+    pub max_total_extension: i64,
+    pub extensions: Vec<GrantedExtension>, // Every extension granted so far
+    pub extension_counter: Option<ExtensionCounterOffer>, // Importer counter-offer awaiting the exporter
+    pub extension_response_window: i64,
+    pub extension_response_deadline: i64, // Pending request can be expired by anyone after this
+    pub verifier_panel: Vec<Pubkey>, // Empty = `verifier` decides alone
+    pub verifier_threshold: u8,
+    pub verifier_votes: Vec<Option<VerifierVote>>, // Current vote of each panel member, by position
+    pub min_fee: u64, // Flat minimum protocol fee per release, fixed at creation from the config or mint
+}
+
+// Lamport vault of a SOL order, at ["escrow_pda", order], or of its exporter bond, at
+// ["bond_pda", order]. It holds the funds on top of its own rent reserve, which goes back to
+// whoever paid it when the vault is closed.
+#[account]
+pub struct SolVault {
+    pub order: Pubkey,
+}
+
+impl SolVault {
+    pub const LEN: usize = 8 + 32;
+}
+
+// Compact record of a closed order, kept for audit
+#[account]
+pub struct SettlementRecord {
+    pub order: Pubkey,
+    pub order_id: [u8; 32],
+    pub importer: Pubkey,
+    pub exporter: Pubkey,
+    pub token_mint: Option<Pubkey>,
+    pub amount: u64,
+    pub released_amount: u64,
+    pub refunded_amount: u64,
+    pub fee_amount: u64,
+    pub final_state: OrderState,
+    pub closed_at: i64,
+    pub bump: u8,
+}
+
+impl SettlementRecord {
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 32 + 33 + 8 + 8 + 8 + 8 + 1 + 8 + 1;
+}
+
+impl Order {
+    pub const LEN: usize = 32 + 1 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 1 + 1 + 8 + 32 + 33 + 1 + 1 + 4 + (8 + 1 + 100) * 10 + 4 + 50 + 200 + 4 + (20 * 5) + 30 + 8 + 4 + (4 + 32 + 8 + 8 + 32 + 1) * MAX_MILESTONES + 8 + 2 + 1 + 1 + 1 + 8 + 2 + 8 + 1 + 1 + 1 + 4 + (32 + 8 + 8 + 8) * MAX_NEGOTIATION_ROUNDS + 1 + 1 + 8 + 4 + (8 + 8 + 8 + 8) * MAX_EXTENSIONS + 1 + 8 + 8 + 8 + 8 + 4 + 32 * MAX_VERIFIERS + 1 + 4 + (1 + 1 + 2) * MAX_VERIFIERS + 8;
+    
+    // Helper function to add history entry
+    pub fn add_history_entry(&mut self, state: OrderState, description: String, timestamp: i64) {
+        let entry = OrderHistoryEntry {
+            timestamp,
+            state: state.clone(),
+            description: truncate_at_char_boundary(&description, 100).to_string(),
+        };
+        
+        // Keep only last 10 history entries to save space
+        if self.history.len() >= 10 {
+            self.history.remove(0);
+        }
+        self.history.push(entry);
+        self.last_updated = timestamp;
+    }
+    
+    // Helper function to account for a protocol fee taken from a release
+    pub fn record_fee(&mut self, fee: u64, timestamp: i64) {
+        if fee == 0 {
+            return;
+        }
+        self.fee_amount += fee;
+        let current_state = self.state.clone();
+        self.add_history_entry(
+            current_state,
+            format!("Protocol fee: {} units to treasury", fee),
+            timestamp,
+        );
+    }
+    
+    // Helper function to validate and store the payment schedule
+    pub fn set_milestones(&mut self, milestones: Vec<MilestoneParams>, creation_time: i64) -> Result<()> {
+        require!(milestones.len() <= MAX_MILESTONES, EscrowError::InvalidMilestones);
+        if milestones.is_empty() {
+            self.milestones = Vec::new();
+            return Ok(());
+        }
+        
+        let mut total: u64 = 0;
+        for milestone in milestones.iter() {
+            require!(milestone.label.len() <= 32, EscrowError::InvalidMilestones);
+            require!(milestone.amount > 0, EscrowError::InvalidMilestones);
+            require!(milestone.deadline > creation_time, EscrowError::InvalidMilestones);
+            total = total.checked_add(milestone.amount).ok_or(EscrowError::InvalidMilestones)?;
+        }
+        // Tranches must account for the whole escrowed amount
+        require!(total == self.amount, EscrowError::InvalidMilestones);
+        
+        self.milestones = milestones
+            .into_iter()
+            .map(|m| Milestone {
+                label: m.label,
+                amount: m.amount,
+                deadline: m.deadline,
+                approver: m.approver,
+                state: MilestoneState::Pending,
+            })
+            .collect();
+        Ok(())
+    }
+    
+    // Helper function to validate and store the optional order terms
+    pub fn set_terms(&mut self, terms: &OrderTerms, creation_time: i64, config: &Config) -> Result<()> {
+        // Time windows are bounded by the longest allowed deadline, so adding them to a
+        // timestamp cannot overflow
+        require!(terms.bond_slash_bps as u64 <= BPS_DENOMINATOR, EscrowError::InvalidBond);
+        require!(
+            terms.grace_period >= 0 && terms.grace_period <= config.max_deadline,
+            EscrowError::InvalidLatePenalty
+        );
+        require!(terms.late_penalty_bps_per_day as u64 <= BPS_DENOMINATOR, EscrowError::InvalidLatePenalty);
+        require!(
+            terms.acceptance_window > 0 && terms.acceptance_window <= config.max_deadline,
+            EscrowError::InvalidAcceptanceWindow
+        );
+        self.bond_amount = terms.bond_amount;
+        self.bond_slash_bps = terms.bond_slash_bps;
+        self.bond_posted = false;
+        self.grace_period = terms.grace_period;
+        self.late_penalty_bps_per_day = terms.late_penalty_bps_per_day;
+        self.acceptance_deadline = creation_time
+            .checked_add(terms.acceptance_window)
+            .ok_or(EscrowError::TimeOverflow)?;
+        self.require_verifier_acceptance = terms.require_verifier_acceptance;
+        self.deadline_mode = terms.deadline_mode.clone();
+        require!(terms.max_extensions as usize <= MAX_EXTENSIONS, EscrowError::InvalidExtensionPolicy);
+        require!(terms.max_total_extension >= 0, EscrowError::InvalidExtensionPolicy);
+        self.max_extensions = terms.max_extensions;
+        self.max_total_extension = terms.max_total_extension;
+        require!(
+            terms.extension_response_window > 0 && terms.extension_response_window <= config.max_deadline,
+            EscrowError::InvalidExtensionPolicy
+        );
+        self.extension_response_window = terms.extension_response_window;
+        self.extension_response_deadline = 0;
+        self.extensions = Vec::new();
+        self.extension_counter = None;
+        self.exporter_accepted = false;
+        self.verifier_accepted = false;
+        
+        let panel = &terms.verifier_panel;
+        require!(panel.len() <= MAX_VERIFIERS, EscrowError::InvalidVerifierPanel);
+        require!(
+            if panel.is_empty() {
+                terms.verifier_threshold == 0
+            } else {
+                terms.verifier_threshold >= 1 && terms.verifier_threshold as usize <= panel.len()
+            },
+            EscrowError::InvalidVerifierPanel
+        );
+        for (i, verifier) in panel.iter().enumerate() {
+            require!(!panel[..i].contains(verifier), EscrowError::InvalidVerifierPanel);
+        }
+        self.verifier_panel = panel.clone();
+        self.verifier_threshold = terms.verifier_threshold;
+        self.verifier_votes = vec![None; panel.len()];
+        Ok(())
+    }
+    
+    // Helper function to append a negotiation round, bounded by MAX_NEGOTIATION_ROUNDS
+    pub fn record_proposal(&mut self, proposer: Pubkey, deadline: i64, amount: u64, timestamp: i64) -> Result<()> {
+        require!(self.proposals.len() < MAX_NEGOTIATION_ROUNDS, EscrowError::NegotiationRoundsExceeded);
+        self.proposals.push(DeadlineProposal {
+            proposer,
+            deadline,
+            amount,
+            timestamp,
+        });
+        Ok(())
+    }
+    
+    // Helper function to sum the time already granted through extensions
+    pub fn total_extension(&self) -> i64 {
+        self.extensions
+            .iter()
+            .map(|e| e.new_deadline - e.previous_deadline)
+            .sum()
+    }
+    
+    // Helper function to grant an extension, clearing the pending request
+    pub fn grant_extension(&mut self, new_deadline: i64, price_reduction: u64, timestamp: i64) {
+        self.extensions.push(GrantedExtension {
+            previous_deadline: self.approved_deadline,
+            new_deadline,
+            price_reduction,
+            timestamp,
+        });
+        self.approved_deadline = new_deadline;
+        self.refunded_amount += price_reduction;
+        self.clear_extension_request();
+    }
+    
+    // Helper function to drop a pending extension request and return to the state it interrupted
+    pub fn clear_extension_request(&mut self) {
+        self.extension_requested = false;
+        self.extension_deadline = 0;
+        self.extension_counter = None;
+        self.extension_response_deadline = 0;
+        self.state = if self.bill_of_lading_hash != [0u8; 32] {
+            OrderState::InTransit
+        } else {
+            OrderState::PendingShipment
+        };
+    }
+    
+    // Number of panel members currently voting for `vote`
+    pub fn panel_votes_for(&self, vote: &VerifierVote) -> u8 {
+        self.verifier_votes
+            .iter()
+            .filter(|v| v.as_ref() == Some(vote))
+            .count() as u8
+    }
+    
+    // Whether the verifier side has signed off `vote`: the single verifier itself, or for
+    // panel orders any panel member once the threshold of matching votes has been reached
+    pub fn verifier_approves(&self, signer: &Pubkey, vote: &VerifierVote) -> bool {
+        if self.verifier_panel.is_empty() {
+            return *signer == self.verifier;
+        }
+        self.verifier_panel.contains(signer) && self.panel_votes_for(vote) >= self.verifier_threshold
+    }
+    
+    // Last moment shipment or delivery is accepted: the approved deadline plus the grace period
+    pub fn final_deadline(&self) -> Result<i64> {
+        Ok(self
+            .approved_deadline
+            .checked_add(self.grace_period)
+            .ok_or(EscrowError::TimeOverflow)?)
+    }
+    
+    // Helper function to compute (days late, penalty) on `amount` for a delivery at `now`;
+    // every started day past the approved deadline counts, capped at the full amount
+    pub fn late_penalty(&self, amount: u64, now: i64) -> (u64, u64) {
+        if now <= self.approved_deadline {
+            return (0, 0);
+        }
+        let days_late = now.abs_diff(self.approved_deadline).div_ceil(SECONDS_PER_DAY as u64);
+        let penalty_bps = days_late as u128 * self.late_penalty_bps_per_day as u128;
+        let penalty = (amount as u128 * penalty_bps / BPS_DENOMINATOR as u128).min(amount as u128) as u64;
+        (days_late, penalty)
+    }
+    
+    // Helper function to split the posted bond into (returned to exporter, slashed to importer)
+    pub fn bond_split(&self, slash: bool) -> (u64, u64) {
+        if !slash {
+            return (self.bond_amount, 0);
+        }
+        let slashed = (self.bond_amount as u128 * self.bond_slash_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        (self.bond_amount - slashed, slashed)
+    }
+    
+    // Helper function to split whatever is still held in escrow into (exporter share, importer
+    // share) for a dispute verdict; the importer gets the rounding remainder
+    pub fn dispute_split(&self, exporter_bps: u16) -> (u64, u64) {
+        let remaining = self.amount - self.released_amount - self.refunded_amount;
+        let exporter_share = (remaining as u128 * exporter_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        (exporter_share, remaining - exporter_share)
+    }
+    
+    // Helper function to record that the bond has been paid out of the bond vault
+    pub fn settle_bond(&mut self, to_exporter: u64, to_importer: u64, timestamp: i64) {
+        self.bond_posted = false;
+        let current_state = self.state.clone();
+        self.add_history_entry(
+            current_state,
+            format!("Bond settled: {} units to exporter, {} units slashed to importer", to_exporter, to_importer),
+            timestamp,
+        );
+    }
+    
+    // Whether the order is funded with an approved deadline and not yet settled or disputed
+    pub fn is_live(&self) -> bool {
+        self.deadline_approved
+            && matches!(
+                self.state,
+                OrderState::PendingShipment
+                    | OrderState::PendingExtensionApproval
+                    | OrderState::InTransit
+                    | OrderState::Delivered
+            )
+    }
+    
+    // Helper function to check the importer may cancel at `now`, with (`mutual`) or without
+    // the exporter's signature
+    pub fn check_cancellable(&self, mutual: bool, now: i64) -> Result<()> {
+        match self.state {
+            OrderState::PendingAcceptance => require!(
+                mutual || now > self.acceptance_deadline,
+                EscrowError::AcceptanceWindowOpen
+            ),
+            OrderState::PendingDeadlineApproval => {},
+            OrderState::PendingShipment => require!(mutual, EscrowError::Unauthorized),
+            _ => return err!(EscrowError::InvalidState),
+        }
+        Ok(())
+    }
+    
+    // Helper function to check the order is final with every unit of escrow and bond paid out
+    pub fn check_closable(&self) -> Result<()> {
+        require!(
+            self.state == OrderState::Completed
+                || self.state == OrderState::Refunded
+                || self.state == OrderState::Cancelled,
+            EscrowError::InvalidState
+        );
+        require!(
+            self.released_amount + self.refunded_amount == self.amount,
+            EscrowError::FundsNotSettled
+        );
+        require!(!self.bond_posted, EscrowError::FundsNotSettled);
+        Ok(())
+    }
+    
+    // Helper function to check the order amount can be amended to `new_amount`. Amounts can
+    // change until the goods ship; while the deadline is still being negotiated,
+    // counter-proposals carry the amount instead
+    pub fn check_amendment(&self, new_amount: u64) -> Result<()> {
+        require!(self.state == OrderState::PendingShipment, EscrowError::InvalidState);
+        require!(self.milestones.is_empty(), EscrowError::MilestoneScheduleActive);
+        require!(
+            new_amount > self.released_amount + self.refunded_amount && new_amount != self.amount,
+            EscrowError::InvalidPartialAmount
+        );
+        Ok(())
+    }
+    
+    // Helper function to update metadata
+    pub fn update_metadata(&mut self, metadata: OrderMetadata, timestamp: i64) {
+        self.metadata = metadata;
+        self.last_updated = timestamp;
+    }
+}
+
+#[account]
+pub struct Config {
+    pub admin: Pubkey,
+    pub treasury: Pubkey, // Receives protocol fees
+    pub min_deadline: i64, // Shortest allowed deadline, in seconds
+    pub max_deadline: i64, // Longest allowed deadline, in seconds
+    pub fee_bps: u16, // Protocol fee in basis points
+    pub min_fee: u64, // Flat minimum fee per release on SOL orders, in lamports
+    pub max_clock_skew: i64, // How far a simulated time may drift from the cluster clock
+    pub paused: bool, // Blocks order creation and payouts while set
+    pub require_registered_verifier: bool, // Orders must name an active, staked registry verifier
+    pub min_verifier_stake: u64, // Lamports a registered verifier needs staked to be chosen
+    pub verifier_unbonding_period: i64, // Seconds between an unbond request and the withdrawal
+    pub bump: u8,
+}
+
+// Verifier registry entry, at ["verifier", authority]. Stake is held as lamports in the
+// account itself and can be slashed to the treasury by the config admin.
+#[account]
+pub struct Verifier {
+    pub authority: Pubkey, // Key the verifier signs orders with
+    pub name_hash: [u8; 32],
+    pub jurisdictions: Vec<[u8; 2]>,
+    pub fee_bps: u16,
+    pub flat_fee: u64,
+    pub active: bool, // Set by the verifier
+    pub suspended: bool, // Set by governance when slashing for misconduct
+    pub stake: u64, // Lamports, excluding the account's rent
+    pub unbonding_amount: u64, // Stake requested for withdrawal, still slashable until released
+    pub unbonding_until: i64, // When the requested stake can be withdrawn
+    pub bump: u8,
+}
+
+impl Verifier {
+    pub const LEN: usize = 8 + 32 + 32 + 4 + 2 * MAX_JURISDICTIONS + 2 + 8 + 1 + 1 + 8 + 8 + 8 + 1;
+    
+    // Helper function to validate and store a verifier's public profile
+    pub fn set_profile(&mut self, profile: VerifierProfile) -> Result<()> {
+        require!(profile.jurisdictions.len() <= MAX_JURISDICTIONS, EscrowError::InvalidVerifierProfile);
+        require!(profile.fee_bps as u64 <= BPS_DENOMINATOR, EscrowError::InvalidVerifierProfile);
+        self.name_hash = profile.name_hash;
+        self.jurisdictions = profile.jurisdictions;
+        self.fee_bps = profile.fee_bps;
+        self.flat_fee = profile.flat_fee;
+        self.active = profile.active;
+        Ok(())
+    }
+    
+    // Helper function to check the verifier can be named on a new order
+    pub fn check_eligible(&self, config: &Config) -> Result<()> {
+        require!(self.active && !self.suspended, EscrowError::VerifierNotActive);
+        require!(self.stake >= config.min_verifier_stake, EscrowError::InsufficientStake);
+        Ok(())
+    }
+    
+    // Helper function to hold panel members to the same registry policy as a single verifier.
+    // Their entries lead the remaining accounts, in panel order; the rest are returned for
+    // Token-2022 transfer hooks.
+    pub fn check_panel<'a, 'info>(
+        panel: &[Pubkey],
+        accounts: &'a [AccountInfo<'info>],
+        config: &Config,
+    ) -> Result<&'a [AccountInfo<'info>]> {
+        if !config.require_registered_verifier || panel.is_empty() {
+            return Ok(accounts);
+        }
+        require!(accounts.len() >= panel.len(), EscrowError::VerifierNotRegistered);
+        let (entries, rest) = accounts.split_at(panel.len());
+        for (member, info) in panel.iter().zip(entries) {
+            require!(info.owner == &crate::ID, EscrowError::VerifierNotRegistered);
+            let registered = Verifier::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+            let expected = Pubkey::create_program_address(
+                &[b"verifier", member.as_ref(), &[registered.bump]],
+                &crate::ID,
+            )
+            .map_err(|_| EscrowError::VerifierNotRegistered)?;
+            require!(info.key() == expected, EscrowError::VerifierNotRegistered);
+            registered.check_eligible(config)?;
+        }
+        Ok(rest)
+    }
+}
+
+// Admin allowlist entry for an SPL mint, at ["allowed_mint", mint]
+#[account]
+pub struct AllowedMint {
+    pub mint: Pubkey,
+    pub enabled: bool, // Disabled mints are kept but refused at order creation
+    pub min_amount: u64, // Smallest order, in the mint's units
+    pub max_amount: u64, // Largest order, 0 = no limit
+    pub min_fee: u64, // Flat minimum protocol fee per release, in the mint's units
+    pub bump: u8,
+}
+
+impl AllowedMint {
+    pub const LEN: usize = 8 + 32 + 1 + 8 + 8 + 8 + 1;
+    
+    // Helper function to check an order amount against the per-mint limits
+    pub fn check_amount(&self, amount: u64) -> Result<()> {
+        require!(
+            amount >= self.min_amount && (self.max_amount == 0 || amount <= self.max_amount),
+            EscrowError::OrderAmountOutOfRange
+        );
+        Ok(())
+    }
+}
+
+impl Config {
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 2 + 8 + 8 + 1 + 1 + 1 + 8 + 8;
+    
+    // Helper function to validate and store admin-supplied settings
+    pub fn apply(&mut self, params: ConfigParams) -> Result<()> {
+        require!(
+            params.min_deadline > 0 && params.min_deadline <= params.max_deadline,
+            EscrowError::InvalidConfig
+        );
+        require!(params.fee_bps as u64 <= BPS_DENOMINATOR, EscrowError::InvalidConfig);
+        require!(params.max_clock_skew >= 0, EscrowError::InvalidConfig);
+        require!(params.verifier_unbonding_period >= 0, EscrowError::InvalidConfig);
+        
+        self.treasury = params.treasury;
+        self.min_deadline = params.min_deadline;
+        self.max_deadline = params.max_deadline;
+        self.fee_bps = params.fee_bps;
+        self.min_fee = params.min_fee;
+        self.max_clock_skew = params.max_clock_skew;
+        self.paused = params.paused;
+        self.require_registered_verifier = params.require_registered_verifier;
+        self.min_verifier_stake = params.min_verifier_stake;
+        self.verifier_unbonding_period = params.verifier_unbonding_period;
+        Ok(())
+    }
+    
+    // Helper function to build the event announcing the current settings
+    pub fn updated_event(&self) -> ConfigUpdated {
+        ConfigUpdated {
+            version: EVENT_VERSION,
+            admin: self.admin,
+            treasury: self.treasury,
+            min_deadline: self.min_deadline,
+            max_deadline: self.max_deadline,
+            fee_bps: self.fee_bps,
+            min_fee: self.min_fee,
+            max_clock_skew: self.max_clock_skew,
+            paused: self.paused,
+            require_registered_verifier: self.require_registered_verifier,
+            min_verifier_stake: self.min_verifier_stake,
+            verifier_unbonding_period: self.verifier_unbonding_period,
+        }
+    }
+    
+    // Helper function to get the time for an instruction: the cluster clock, or a caller-supplied
+    // time for off-chain simulations when it is within the configured skew. Instructions that
+    // move funds read the cluster clock directly instead
+    pub fn resolve_time(&self, simulated_time: Option<i64>) -> Result<i64> {
+        self.resolve_time_at(simulated_time, Clock::get()?.unix_timestamp)
+    }
+    
+    // `resolve_time` against a given cluster time
+    pub fn resolve_time_at(&self, simulated_time: Option<i64>, now: i64) -> Result<i64> {
+        match simulated_time {
+            None => Ok(now),
+            Some(time) => {
+                require!(
+                    self.max_clock_skew > 0 && time.abs_diff(now) <= self.max_clock_skew as u64,
+                    EscrowError::ClockSkewExceeded
+                );
+                Ok(time)
+            }
+        }
+    }
+    
+    // Helper function to check that an absolute deadline is within the configured range from now
+    pub fn check_deadline(&self, deadline: i64, now: i64) -> Result<()> {
+        let time_until_deadline = deadline.saturating_sub(now);
+        require!(
+            time_until_deadline >= self.min_deadline,
+            EscrowError::DeadlineTooShort
+        );
+        require!(
+            time_until_deadline <= self.max_deadline,
+            EscrowError::DeadlineTooLong
+        );
+        Ok(())
+    }
+    
+    // Helper function to compute the protocol fee on a release with the order's flat minimum,
+    // never more than the release itself
+    pub fn protocol_fee(&self, amount: u64, min_fee: u64) -> u64 {
+        let bps_fee = (amount as u128 * self.fee_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        bps_fee.max(min_fee).min(amount)
+    }
+}
+
+// Accounts needed to pay out of one of an order's vaults with its PDA signature: the escrow
+// (`escrow_pda` / `escrow_vault`) or the exporter bond (`bond_pda` / `bond_vault`)
+struct EscrowVault<'a, 'info> {
+    seed: &'static [u8],
+    order_key: Pubkey,
+    bump: u8,
+    token_mint: Option<Pubkey>,
+    authority: &'a AccountInfo<'info>,
+    token_vault: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    token_program: Option<&'a Interface<'info, TokenInterface>>,
+    mint: Option<&'a InterfaceAccount<'info, Mint>>,
+    remaining_accounts: &'a [AccountInfo<'info>], // Extra accounts for Token-2022 transfer hooks
+    system_program: &'a Program<'info, System>,
+}
+
+impl<'a, 'info> EscrowVault<'a, 'info> {
+    // The order's escrow: lamports in `escrow_pda`, or tokens in the `escrow_vault` it signs for
+    fn escrow(
+        order: &Account<'info, Order>,
+        escrow_pda: &'a AccountInfo<'info>,
+        token_vault: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+        token_program: Option<&'a Interface<'info, TokenInterface>>,
+        mint: Option<&'a InterfaceAccount<'info, Mint>>,
+        remaining_accounts: &'a [AccountInfo<'info>],
+        system_program: &'a Program<'info, System>,
+    ) -> Self {
+        EscrowVault {
+            seed: b"escrow_pda",
+            order_key: order.key(),
+            bump: order.escrow_bump,
+            token_mint: order.token_mint,
+            authority: escrow_pda,
+            token_vault,
+            token_program,
+            mint,
+            remaining_accounts,
+            system_program,
+        }
+    }
+    
+    // The exporter's bond: lamports in `bond_pda`, or tokens in the `bond_vault` it signs for
+    fn bond(
+        order: &Account<'info, Order>,
+        bond_pda: &'a AccountInfo<'info>,
+        token_vault: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+        token_program: Option<&'a Interface<'info, TokenInterface>>,
+        mint: Option<&'a InterfaceAccount<'info, Mint>>,
+        remaining_accounts: &'a [AccountInfo<'info>],
+        system_program: &'a Program<'info, System>,
+    ) -> Self {
+        EscrowVault {
+            seed: b"bond_pda",
+            order_key: order.key(),
+            bump: order.bond_bump,
+            token_mint: order.token_mint,
+            authority: bond_pda,
+            token_vault,
+            token_program,
+            mint,
+            remaining_accounts,
+            system_program,
+        }
+    }
+    
+    // Transfer `amount` from the vault to the recipient (lamports for SOL orders,
+    // `recipient_token_account` for SPL orders)
+    fn pay(
+        &self,
+        recipient: &AccountInfo<'info>,
+        recipient_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+        amount: u64,
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        let seeds = &[
+            self.seed,
+            self.order_key.as_ref(),
+            &[self.bump],
+        ];
+        let signer = &[&seeds[..]];
+        match self.token_mint {
+            // Program-owned SOL vault: move lamports directly, never dipping into the rent reserve
+            None if self.authority.owner == &crate::ID => {
+                let reserve = Rent::get()?.minimum_balance(self.authority.data_len());
+                let balance = self.authority.lamports();
+                require!(
+                    balance.saturating_sub(amount) >= reserve,
+                    EscrowError::InsufficientVaultBalance
+                );
+                **self.authority.try_borrow_mut_lamports()? = balance - amount;
+                **recipient.try_borrow_mut_lamports()? += amount;
+            },
+            None => {
+                system_program::transfer(
+                    CpiContext::new_with_signer(
+                        self.system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: self.authority.clone(),
+                            to: recipient.clone(),
+                        },
+                        signer,
+                    ),
+                    amount,
+                )?;
+            },
+            Some(_mint) => {
+                let token_vault = self.token_vault.ok_or(EscrowError::MissingSPLAccount)?;
+                let recipient_token_account = recipient_token_account.ok_or(EscrowError::MissingSPLAccount)?;
+                self.transfer_checked(
+                    token_vault.to_account_info(),
+                    recipient_token_account.to_account_info(),
+                    self.authority.clone(),
+                    amount,
+                    signer,
+                )?;
+            }
+        }
+        Ok(())
+    }
+    
+    // Transfer `amount` into the vault from `depositor`, who must sign the transaction.
+    // Returns what the vault actually received, which is less than `amount` for
+    // Token-2022 mints that charge a transfer fee.
+    fn deposit(
+        &self,
+        depositor: &AccountInfo<'info>,
+        depositor_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+        amount: u64,
+    ) -> Result<u64> {
+        if amount == 0 {
+            return Ok(0);
+        }
+        match self.token_mint {
+            None => {
+                system_program::transfer(
+                    CpiContext::new(
+                        self.system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: depositor.clone(),
+                            to: self.authority.clone(),
+                        },
+                    ),
+                    amount,
+                )?;
+                Ok(amount)
+            },
+            Some(mint) => {
+                let token_vault = self.token_vault.ok_or(EscrowError::MissingSPLAccount)?;
+                let before = token_balance(token_vault)?;
+                match depositor_token_account {
+                    Some(depositor_token_account) => self.transfer_checked(
+                        depositor_token_account.to_account_info(),
+                        token_vault.to_account_info(),
+                        depositor.clone(),
+                        amount,
+                        &[],
+                    )?,
+                    // Wrapped SOL can be funded straight from lamports
+                    None => {
+                        require!(is_native_mint(&mint), EscrowError::MissingSPLAccount);
+                        self.wrap_lamports(depositor, token_vault, amount)?;
+                    }
+                }
+                let received = token_balance(token_vault)? - before;
+                require!(received > 0, EscrowError::InvalidPartialAmount);
+                Ok(received)
+            }
+        }
+    }
+    
+    // Move lamports into a wrapped-SOL vault and sync its token balance
+    fn wrap_lamports(
+        &self,
+        depositor: &AccountInfo<'info>,
+        token_vault: &InterfaceAccount<'info, TokenAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        let token_program = self.token_program.ok_or(EscrowError::MissingSPLAccount)?;
+        system_program::transfer(
+            CpiContext::new(
+                self.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: depositor.clone(),
+                    to: token_vault.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        let cpi_accounts = token_interface::SyncNative {
+            account: token_vault.to_account_info(),
+        };
+        token_interface::sync_native(CpiContext::new(
+            token_program.to_account_info(),
+            cpi_accounts,
+        ))
+    }
+    
+    // `transfer_checked` through either token program, forwarding any transfer-hook accounts
+    fn transfer_checked(
+        &self,
+        from: AccountInfo<'info>,
+        to: AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        amount: u64,
+        signer: &[&[&[u8]]],
+    ) -> Result<()> {
+        let token_program = self.token_program.ok_or(EscrowError::MissingSPLAccount)?;
+        let mint = self.mint.ok_or(EscrowError::MissingSPLAccount)?;
+        spl_token_2022::onchain::invoke_transfer_checked(
+            token_program.key,
+            from,
+            mint.to_account_info(),
+            to,
+            authority,
+            self.remaining_accounts,
+            amount,
+            mint.decimals,
+            signer,
+        )?;
+        Ok(())
+    }
+    
+    // Close the vault, returning its rent to `destination`: the SPL token vault, or the
+    // program-owned SOL vault (data-less SOL PDAs have nothing to close). Tokens still in an
+    // SPL vault, such as a balance donated by a third party, are swept to
+    // `leftover_token_account` first, since the token program won't close a non-empty account
+    fn close_vault(
+        &self,
+        destination: &AccountInfo<'info>,
+        leftover_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    ) -> Result<()> {
+        if self.token_mint.is_none() {
+            if self.authority.owner == &crate::ID {
+                let lamports = self.authority.lamports();
+                **self.authority.try_borrow_mut_lamports()? = 0;
+                **destination.try_borrow_mut_lamports()? += lamports;
+                self.authority.assign(&System::id());
+                self.authority.resize(0)?;
+            }
+            return Ok(());
+        }
+        let token_vault = self.token_vault.ok_or(EscrowError::MissingSPLAccount)?;
+        let token_program = self.token_program.ok_or(EscrowError::MissingSPLAccount)?;
+        let seeds = &[
+            self.seed,
+            self.order_key.as_ref(),
+            &[self.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let leftover = token_balance(token_vault)?;
+        if leftover > 0 {
+            let leftover_token_account = leftover_token_account.ok_or(EscrowError::MissingSPLAccount)?;
+            self.transfer_checked(
+                token_vault.to_account_info(),
+                leftover_token_account.to_account_info(),
+                self.authority.clone(),
+                leftover,
+                signer,
+            )?;
+        }
+        // Token-2022 refuses to close an account holding withheld transfer fees, so sweep them
+        // to the mint first; harvesting is permissionless
+        if withheld_fees(token_vault)? > 0 {
+            let mint = self.mint.ok_or(EscrowError::MissingSPLAccount)?;
+            token_2022_extensions::harvest_withheld_tokens_to_mint(
+                CpiContext::new(
+                    token_program.to_account_info(),
+                    token_2022_extensions::HarvestWithheldTokensToMint {
+                        token_program_id: token_program.to_account_info(),
+                        mint: mint.to_account_info(),
+                    },
+                ),
+                vec![token_vault.to_account_info()],
+            )?;
+        }
+        let cpi_accounts = token_interface::CloseAccount {
+            account: token_vault.to_account_info(),
+            destination: destination.clone(),
+            authority: self.authority.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        token_interface::close_account(cpi_ctx)
+    }
+}
+
+// The longest prefix of `text` that fits in `max_len` bytes without splitting a character
+fn truncate_at_char_boundary(text: &str, max_len: usize) -> &str {
+    if text.len() <= max_len {
+        return text;
+    }
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+// Whether `mint` is wrapped SOL under either token program
+fn is_native_mint(mint: &Pubkey) -> bool {
+    *mint == spl_token::native_mint::ID || *mint == spl_token_2022::native_mint::ID
+}
+
+// Transfer fees withheld in a Token-2022 account, 0 for accounts without the extension
+fn withheld_fees(token_account: &InterfaceAccount<TokenAccount>) -> Result<u64> {
+    let info = token_account.to_account_info();
+    if info.owner != &spl_token_2022::ID {
+        return Ok(0);
+    }
+    let data = info.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+    Ok(state
+        .get_extension::<TransferFeeAmount>()
+        .map(|fee| u64::from(fee.withheld_amount))
+        .unwrap_or(0))
+}
+
+// Current balance of a token account, read from its data so it reflects CPIs made in this instruction
+fn token_balance(token_account: &InterfaceAccount<TokenAccount>) -> Result<u64> {
+    let info = token_account.to_account_info();
+    let data = info.try_borrow_data()?;
+    Ok(TokenAccount::try_deserialize(&mut &data[..])?.amount)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum OrderState {
+    PendingDeadlineApproval,  // Waiting for importer to approve deadline
+    PendingShipment,          // Deadline approved, waiting for shipment
+    PendingExtensionApproval, // Waiting for importer to approve deadline extension
+    InTransit,
+    Delivered,
+    Completed,
+    Refunded,
+    Disputed,
+    Cancelled,                // Called off before shipment, funds returned to importer
+    PendingAcceptance,        // Waiting for exporter (and optionally verifier) to accept the order
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum DeadlineMode {
+    Absolute, // Proposed deadlines are unix timestamps
+    Relative, // Proposed deadlines are durations in seconds, counted from approval
+}
+
+impl DeadlineMode {
+    // Absolute timestamp of a proposed deadline if the clock starts at `from`; a duration
+    // too large to add is rejected as too long
+    pub fn resolve(&self, deadline: i64, from: i64) -> Result<i64> {
+        match self {
+            DeadlineMode::Absolute => Ok(deadline),
+            DeadlineMode::Relative => Ok(from.checked_add(deadline).ok_or(EscrowError::DeadlineTooLong)?),
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum VerifierVote {
+    ConfirmDelivery,
+    ResolveDispute { exporter_bps: u16 },
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum MilestoneState {
+    Pending,   // Waiting for exporter to submit
+    Submitted, // Waiting for approver sign-off
+    Approved,  // Ready to be released to exporter
+    Released,
+    Refunded,
+}
+
+// Version of the event payloads below, bumped whenever their layout changes
+pub const EVENT_VERSION: u8 = 1;
+
+#[event]
+pub struct ConfigUpdated {
+    pub version: u8,
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+    pub min_deadline: i64,
+    pub max_deadline: i64,
+    pub fee_bps: u16,
+    pub min_fee: u64,
+    pub max_clock_skew: i64,
+    pub paused: bool,
+    pub require_registered_verifier: bool,
+    pub min_verifier_stake: u64,
+    pub verifier_unbonding_period: i64,
+}
+
+#[event]
+pub struct AllowedMintUpdated {
+    pub version: u8,
+    pub mint: Pubkey,
+    pub enabled: bool,
+    pub min_amount: u64,
+    pub max_amount: u64,
+    pub min_fee: u64,
+    pub removed: bool, // Entry closed; the mint is no longer accepted
+}
+
+#[event]
+pub struct VerifierUpdated {
+    pub version: u8,
+    pub verifier: Pubkey,
+    pub active: bool,
+    pub suspended: bool,
+    pub stake: u64,
+}
+
+#[event]
+pub struct VerifierUnbonding {
+    pub version: u8,
+    pub verifier: Pubkey,
+    pub amount: u64,
+    pub unbonding_until: i64,
+}
+
+#[event]
+pub struct VerifierSlashed {
+    pub version: u8,
+    pub verifier: Pubkey,
+    pub amount: u64,
+    pub remaining_stake: u64,
+    pub suspended: bool,
+    pub reason: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderCreated {
+    pub version: u8,
+    pub order: Pubkey,
+    pub importer: Pubkey,
+    pub exporter: Pubkey,
+    pub verifier: Pubkey,
+    pub token_mint: Option<Pubkey>, // None = SOL
+    pub amount: u64,
+    pub proposed_deadline: i64,
+    pub milestone_count: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DeadlineProposed {
+    pub version: u8,
+    pub order: Pubkey,
+    pub proposed_by: Pubkey,
+    pub proposed_deadline: i64,
+    pub amount: u64,
+    pub round: u8, // 1 = initial terms
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DeadlineApproved {
+    pub version: u8,
+    pub order: Pubkey,
+    pub approved_by: Pubkey,
+    pub proposed_deadline: i64,
+    pub approved_deadline: i64,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GoodsShipped {
+    pub version: u8,
+    pub order: Pubkey,
+    pub bill_of_lading_hash: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DeliveryConfirmed {
+    pub version: u8,
+    pub order: Pubkey,
+    pub confirmed_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FundsReleased {
+    pub version: u8,
+    pub order: Pubkey,
+    pub amount: u64, // Net amount paid to exporter
+    pub fee: u64, // Protocol fee paid to treasury
+    pub milestone: Option<u8>, // Milestone index for scheduled releases
+    pub total_released: u64, // Order's released_amount after this release
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Refunded {
+    pub version: u8,
+    pub order: Pubkey,
+    pub amount: u64,
+    pub milestone: Option<u8>, // Milestone index for scheduled refunds
+    pub total_refunded: u64, // Order's refunded_amount after this refund
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Disputed {
+    pub version: u8,
+    pub order: Pubkey,
+    pub disputed_by: Pubkey,
+    pub reason: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VerifierVoted {
+    pub version: u8,
+    pub order: Pubkey,
+    pub verifier: Pubkey,
+    pub vote: VerifierVote,
+    pub votes: u8, // Panel votes now matching this one
+    pub threshold: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DisputeResolved {
+    pub version: u8,
+    pub order: Pubkey,
+    pub exporter_bps: u16,
+    pub exporter_amount: u64, // Net of protocol fee
+    pub importer_amount: u64,
+    pub fee: u64,
+    pub resolution: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ExtensionRequested {
+    pub version: u8,
+    pub order: Pubkey,
+    pub current_deadline: i64,
+    pub extension_deadline: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ExtensionApproved {
+    pub version: u8,
+    pub order: Pubkey,
+    pub approved_deadline: i64,
+    pub price_reduction: u64, // Refunded to importer under an accepted counter-offer
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ExtensionCounterOffered {
+    pub version: u8,
+    pub order: Pubkey,
+    pub requested_deadline: i64,
+    pub counter_deadline: i64,
+    pub price_reduction: u64,
+    pub timestamp: i64,
 }
 
-impl Order {
-    pub const LEN: usize = 32 + 32 + 32 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 1 + 1 + 8 + 32 + 33 + 4 + (8 + 1 + 100) * 10 + 4 + 50 + 200 + 4 + (20 * 5) + 30 + 8;
-    
-    // Helper function to add history entry
-    pub fn add_history_entry(&mut self, state: OrderState, description: String, timestamp: i64) {
-        let entry = OrderHistoryEntry {
-            timestamp,
-            state: state.clone(),
-            description: if description.len() > 100 {
-                description[..100].to_string()
-            } else {
-                description
-            },
-        };
-        
-        // Keep only last 10 history entries to save space
-        if self.history.len() >= 10 {
-            self.history.remove(0);
-        }
-        self.history.push(entry);
-        self.last_updated = timestamp;
-    }
-    
-    // Helper function to update metadata
-    pub fn update_metadata(&mut self, metadata: OrderMetadata, timestamp: i64) {
-        self.metadata = metadata;
-        self.last_updated = timestamp;
-    }
+#[event]
+pub struct ExtensionRejected {
+    pub version: u8,
+    pub order: Pubkey,
+    pub rejected_deadline: i64,
+    pub timestamp: i64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
-pub enum OrderState {
-    PendingDeadlineApproval,  // Waiting for importer to approve deadline
-    PendingShipment,          // Deadline approved, waiting for shipment
-    PendingExtensionApproval, // Waiting for importer to approve deadline extension
-    InTransit,
-    Delivered,
-    Completed,
-    Refunded,
-    Disputed,
+#[event]
+pub struct ExtensionExpired {
+    pub version: u8,
+    pub order: Pubkey,
+    pub expired_deadline: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MetadataUpdated {
+    pub version: u8,
+    pub order: Pubkey,
+    pub updated_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MilestoneSubmitted {
+    pub version: u8,
+    pub order: Pubkey,
+    pub index: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MilestoneApproved {
+    pub version: u8,
+    pub order: Pubkey,
+    pub index: u8,
+    pub approver: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderAmountAmended {
+    pub version: u8,
+    pub order: Pubkey,
+    pub old_amount: u64,
+    pub new_amount: u64, // What the vault now holds for the order
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderCancelled {
+    pub version: u8,
+    pub order: Pubkey,
+    pub mutual: bool, // Whether the exporter co-signed
+    pub refunded_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderClosed {
+    pub version: u8,
+    pub order: Pubkey,
+    pub archived: bool, // Whether a SettlementRecord was written
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BondPosted {
+    pub version: u8,
+    pub order: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BondSettled {
+    pub version: u8,
+    pub order: Pubkey,
+    pub returned_to_exporter: u64,
+    pub slashed_to_importer: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LatePenaltyApplied {
+    pub version: u8,
+    pub order: Pubkey,
+    pub days_late: u64,
+    pub penalty_bps_per_day: u16,
+    pub penalty: u64, // Refunded to importer
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderAccepted {
+    pub version: u8,
+    pub order: Pubkey,
+    pub signer: Pubkey,
+    pub accepted: bool, // Whether every required party has now accepted
+    pub timestamp: i64,
 }
 
 #[error_code]
@@ -1014,4 +4360,1069 @@ pub enum EscrowError {
     InvalidPartialAmount,
     #[msg("Missing SPL token account(s) for SPL payment")] 
     MissingSPLAccount,
+    #[msg("Invalid dispute split - basis points cannot exceed 10000")] 
+    InvalidSplit,
+    #[msg("Invalid config parameters")] 
+    InvalidConfig,
+    #[msg("Program is paused")] 
+    ProgramPaused,
+    #[msg("Treasury account does not match config")] 
+    InvalidTreasury,
+    #[msg("Invalid milestone schedule")] 
+    InvalidMilestones,
+    #[msg("Milestone not found")] 
+    MilestoneNotFound,
+    #[msg("Invalid milestone state for this operation")] 
+    InvalidMilestoneState,
+    #[msg("Order funds are released through its milestone schedule")] 
+    MilestoneScheduleActive,
+    #[msg("Token account does not belong to the order party or mint")] 
+    InvalidTokenAccount,
+    #[msg("Simulated time is not allowed or too far from the cluster clock")] 
+    ClockSkewExceeded,
+    #[msg("Escrowed funds are not fully settled")] 
+    FundsNotSettled,
+    #[msg("Invalid bond terms or bond already posted")] 
+    InvalidBond,
+    #[msg("Exporter bond has not been posted")] 
+    BondNotPosted,
+    #[msg("Missing bond account(s) for a posted bond")] 
+    MissingBondAccount,
+    #[msg("Invalid grace period or late penalty")] 
+    InvalidLatePenalty,
+    #[msg("Missing importer account(s) for a late-delivery penalty")] 
+    MissingPenaltyAccount,
+    #[msg("Acceptance window has expired")] 
+    AcceptanceExpired,
+    #[msg("Acceptance window is still open")] 
+    AcceptanceWindowOpen,
+    #[msg("Acceptance window must be positive")] 
+    InvalidAcceptanceWindow,
+    #[msg("Accepted terms do not match the order")] 
+    TermsMismatch,
+    #[msg("Order already accepted by this party")] 
+    AlreadyAccepted,
+    #[msg("Maximum number of negotiation rounds reached")] 
+    NegotiationRoundsExceeded,
+    #[msg("Extension must move the deadline later, within the requested extension")] 
+    InvalidExtension,
+    #[msg("Extension count or cumulative extension limit reached")] 
+    ExtensionLimitReached,
+    #[msg("Invalid extension policy")] 
+    InvalidExtensionPolicy,
+    #[msg("Extension response window has passed")] 
+    ExtensionExpired,
+    #[msg("Extension response window is still open")] 
+    ExtensionResponseWindowOpen,
+    #[msg("Token mint is not on the allowlist")] 
+    MintNotAllowed,
+    #[msg("Order amount outside the limits for this mint")] 
+    OrderAmountOutOfRange,
+    #[msg("Payout would leave the vault below its rent reserve")] 
+    InsufficientVaultBalance,
+    #[msg("Invalid verifier panel or threshold")] 
+    InvalidVerifierPanel,
+    #[msg("Invalid verifier profile")] 
+    InvalidVerifierProfile,
+    #[msg("Verifier is not in the registry")] 
+    VerifierNotRegistered,
+    #[msg("Verifier is inactive or suspended")] 
+    VerifierNotActive,
+    #[msg("Insufficient verifier stake")] 
+    InsufficientStake,
+    #[msg("Verifier must be inactive to withdraw stake")] 
+    VerifierStillActive,
+    #[msg("No stake withdrawal has been requested")] 
+    NoUnbondingRequest,
+    #[msg("Stake is still unbonding")] 
+    UnbondingPeriodActive,
+    #[msg("Time calculation overflowed")] 
+    TimeOverflow,
+    #[msg("An order with this reference was already settled and archived")] 
+    OrderIdSettled,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::program_pack::Pack;
+
+    // Zeroed accounts decode as empty vectors, `None`s and the first enum variants
+    fn blank_config() -> Config {
+        <Config as AnchorDeserialize>::deserialize(&mut &vec![0u8; Config::LEN][..]).unwrap()
+    }
+
+    fn blank_order() -> Order {
+        <Order as AnchorDeserialize>::deserialize(&mut &vec![0u8; Order::LEN][..]).unwrap()
+    }
+
+    #[test]
+    fn protocol_fee_takes_bps_share() {
+        let mut config = blank_config();
+        config.fee_bps = 250;
+        assert_eq!(config.protocol_fee(10_000, 0), 250);
+        assert_eq!(config.protocol_fee(0, 0), 0);
+    }
+
+    #[test]
+    fn protocol_fee_applies_minimum_capped_at_amount() {
+        let mut config = blank_config();
+        config.fee_bps = 100;
+        assert_eq!(config.protocol_fee(1_000, 50), 50);
+        assert_eq!(config.protocol_fee(100_000, 50), 1_000);
+        assert_eq!(config.protocol_fee(30, 50), 30);
+    }
+
+    #[test]
+    fn protocol_fee_does_not_overflow() {
+        let mut config = blank_config();
+        config.fee_bps = BPS_DENOMINATOR as u16;
+        assert_eq!(config.protocol_fee(u64::MAX, 0), u64::MAX);
+    }
+
+    fn milestone(amount: u64, deadline: i64) -> MilestoneParams {
+        MilestoneParams {
+            label: "tranche".to_string(),
+            amount,
+            deadline,
+            approver: Pubkey::new_unique(),
+        }
+    }
+
+    #[test]
+    fn set_milestones_accepts_schedule_covering_amount() {
+        let mut order = blank_order();
+        order.amount = 1_000;
+        order
+            .set_milestones(vec![milestone(400, 200), milestone(600, 300)], 100)
+            .unwrap();
+        assert_eq!(order.milestones.len(), 2);
+        assert!(order.milestones.iter().all(|m| m.state == MilestoneState::Pending));
+    }
+
+    #[test]
+    fn set_milestones_rejects_invalid_schedules() {
+        let mut order = blank_order();
+        order.amount = 1_000;
+        let err: Error = EscrowError::InvalidMilestones.into();
+        // Tranches must add up to the escrowed amount
+        assert_eq!(order.set_milestones(vec![milestone(400, 200)], 100).unwrap_err(), err);
+        // Deadlines must be after creation
+        assert_eq!(order.set_milestones(vec![milestone(1_000, 100)], 100).unwrap_err(), err);
+        assert_eq!(
+            order.set_milestones(vec![milestone(0, 200), milestone(1_000, 200)], 100).unwrap_err(),
+            err
+        );
+        assert_eq!(
+            order.set_milestones(vec![milestone(u64::MAX, 200), milestone(1, 200)], 100).unwrap_err(),
+            err
+        );
+        let too_many = (0..=MAX_MILESTONES).map(|_| milestone(1, 200)).collect();
+        assert_eq!(order.set_milestones(too_many, 100).unwrap_err(), err);
+    }
+
+    #[test]
+    fn set_milestones_allows_empty_schedule() {
+        let mut order = blank_order();
+        order.amount = 1_000;
+        order.set_milestones(Vec::new(), 100).unwrap();
+        assert!(order.milestones.is_empty());
+    }
+
+    fn bounded_config() -> Config {
+        let mut config = blank_config();
+        config.min_deadline = 3_600;
+        config.max_deadline = 30 * SECONDS_PER_DAY;
+        config
+    }
+
+    fn valid_terms() -> OrderTerms {
+        OrderTerms {
+            bond_amount: 0,
+            bond_slash_bps: 0,
+            grace_period: 0,
+            late_penalty_bps_per_day: 0,
+            acceptance_window: SECONDS_PER_DAY,
+            require_verifier_acceptance: false,
+            deadline_mode: DeadlineMode::Absolute,
+            max_extensions: 0,
+            max_total_extension: 0,
+            extension_response_window: SECONDS_PER_DAY,
+            verifier_panel: Vec::new(),
+            verifier_threshold: 0,
+        }
+    }
+
+    #[test]
+    fn bond_split_returns_whole_bond_unless_slashed() {
+        let mut order = blank_order();
+        order.bond_amount = 1_000;
+        order.bond_slash_bps = 2_500;
+        assert_eq!(order.bond_split(false), (1_000, 0));
+        assert_eq!(order.bond_split(true), (750, 250));
+    }
+
+    #[test]
+    fn bond_split_full_slash_and_rounding() {
+        let mut order = blank_order();
+        order.bond_amount = 999;
+        order.bond_slash_bps = BPS_DENOMINATOR as u16;
+        assert_eq!(order.bond_split(true), (0, 999));
+        // The slashed share rounds down, in the exporter's favour
+        order.bond_slash_bps = 3_333;
+        assert_eq!(order.bond_split(true), (667, 332));
+    }
+
+    #[test]
+    fn set_terms_rejects_slash_above_100_percent() {
+        let mut order = blank_order();
+        let mut terms = valid_terms();
+        terms.bond_amount = 1_000;
+        terms.bond_slash_bps = BPS_DENOMINATOR as u16 + 1;
+        assert_eq!(
+            order.set_terms(&terms, 0, &bounded_config()).unwrap_err(),
+            EscrowError::InvalidBond.into()
+        );
+        terms.bond_slash_bps = BPS_DENOMINATOR as u16;
+        order.set_terms(&terms, 0, &bounded_config()).unwrap();
+        assert!(!order.bond_posted);
+    }
+
+    #[test]
+    fn late_penalty_counts_started_days() {
+        let mut order = blank_order();
+        order.approved_deadline = 1_000;
+        order.late_penalty_bps_per_day = 100;
+        assert_eq!(order.late_penalty(10_000, 1_000), (0, 0));
+        assert_eq!(order.late_penalty(10_000, 1_001), (1, 100));
+        assert_eq!(order.late_penalty(10_000, 1_000 + SECONDS_PER_DAY), (1, 100));
+        assert_eq!(order.late_penalty(10_000, 1_001 + SECONDS_PER_DAY), (2, 200));
+    }
+
+    #[test]
+    fn late_penalty_is_capped_at_amount() {
+        let mut order = blank_order();
+        order.approved_deadline = 0;
+        order.late_penalty_bps_per_day = BPS_DENOMINATOR as u16;
+        assert_eq!(order.late_penalty(500, 3 * SECONDS_PER_DAY), (3, 500));
+        assert_eq!(order.late_penalty(u64::MAX, i64::MAX).1, u64::MAX);
+    }
+
+    #[test]
+    fn final_deadline_adds_grace_period() {
+        let mut order = blank_order();
+        order.approved_deadline = 1_000;
+        order.grace_period = 500;
+        assert_eq!(order.final_deadline().unwrap(), 1_500);
+        order.approved_deadline = i64::MAX;
+        assert_eq!(order.final_deadline().unwrap_err(), EscrowError::TimeOverflow.into());
+    }
+
+    #[test]
+    fn set_terms_bounds_grace_period_and_penalty() {
+        let config = bounded_config();
+        let mut order = blank_order();
+        let mut terms = valid_terms();
+        terms.grace_period = config.max_deadline;
+        terms.late_penalty_bps_per_day = 50;
+        order.set_terms(&terms, 0, &config).unwrap();
+        assert_eq!(order.grace_period, config.max_deadline);
+
+        let err: Error = EscrowError::InvalidLatePenalty.into();
+        terms.grace_period = -1;
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+        terms.grace_period = i64::MAX;
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+        terms.grace_period = 0;
+        terms.late_penalty_bps_per_day = BPS_DENOMINATOR as u16 + 1;
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+    }
+
+    #[test]
+    fn set_terms_sets_acceptance_deadline_within_bounds() {
+        let config = bounded_config();
+        let mut order = blank_order();
+        let mut terms = valid_terms();
+        order.set_terms(&terms, 1_000, &config).unwrap();
+        assert_eq!(order.acceptance_deadline, 1_000 + SECONDS_PER_DAY);
+        assert!(!order.exporter_accepted && !order.verifier_accepted);
+
+        let err: Error = EscrowError::InvalidAcceptanceWindow.into();
+        terms.acceptance_window = 0;
+        assert_eq!(order.set_terms(&terms, 1_000, &config).unwrap_err(), err);
+        terms.acceptance_window = config.max_deadline + 1;
+        assert_eq!(order.set_terms(&terms, 1_000, &config).unwrap_err(), err);
+    }
+
+    #[test]
+    fn record_proposal_caps_negotiation_rounds() {
+        let mut order = blank_order();
+        let importer = Pubkey::new_unique();
+        for round in 0..MAX_NEGOTIATION_ROUNDS {
+            order.record_proposal(importer, 1_000 + round as i64, 500, round as i64).unwrap();
+        }
+        assert_eq!(order.proposals.len(), MAX_NEGOTIATION_ROUNDS);
+        assert_eq!(order.proposals.last().unwrap().deadline, 1_000 + MAX_NEGOTIATION_ROUNDS as i64 - 1);
+        assert_eq!(
+            order.record_proposal(importer, 2_000, 500, 0).unwrap_err(),
+            EscrowError::NegotiationRoundsExceeded.into()
+        );
+    }
+
+    #[test]
+    fn deadline_mode_resolves_absolute_and_relative() {
+        assert_eq!(DeadlineMode::Absolute.resolve(5_000, 1_000).unwrap(), 5_000);
+        assert_eq!(DeadlineMode::Relative.resolve(5_000, 1_000).unwrap(), 6_000);
+        assert_eq!(
+            DeadlineMode::Relative.resolve(i64::MAX, 1_000).unwrap_err(),
+            EscrowError::DeadlineTooLong.into()
+        );
+    }
+
+    #[test]
+    fn check_deadline_enforces_config_bounds() {
+        let config = bounded_config();
+        let now = 1_000;
+        config.check_deadline(now + config.min_deadline, now).unwrap();
+        config.check_deadline(now + config.max_deadline, now).unwrap();
+        assert_eq!(
+            config.check_deadline(now + config.min_deadline - 1, now).unwrap_err(),
+            EscrowError::DeadlineTooShort.into()
+        );
+        assert_eq!(
+            config.check_deadline(now + config.max_deadline + 1, now).unwrap_err(),
+            EscrowError::DeadlineTooLong.into()
+        );
+        assert_eq!(
+            config.check_deadline(i64::MIN, now).unwrap_err(),
+            EscrowError::DeadlineTooShort.into()
+        );
+    }
+
+    #[test]
+    fn set_terms_validates_extension_policy() {
+        let config = bounded_config();
+        let mut order = blank_order();
+        let mut terms = valid_terms();
+        terms.max_extensions = MAX_EXTENSIONS as u8;
+        terms.max_total_extension = 7 * SECONDS_PER_DAY;
+        order.set_terms(&terms, 0, &config).unwrap();
+
+        let err: Error = EscrowError::InvalidExtensionPolicy.into();
+        terms.max_extensions = MAX_EXTENSIONS as u8 + 1;
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+        terms.max_extensions = 1;
+        terms.max_total_extension = -1;
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+    }
+
+    #[test]
+    fn grant_extension_logs_and_accumulates() {
+        let mut order = blank_order();
+        order.approved_deadline = 1_000;
+        order.extension_requested = true;
+        order.state = OrderState::PendingExtensionApproval;
+        order.grant_extension(1_500, 20, 900);
+        order.grant_extension(1_700, 0, 950);
+        assert_eq!(order.approved_deadline, 1_700);
+        assert_eq!(order.total_extension(), 700);
+        assert_eq!(order.refunded_amount, 20);
+        assert_eq!(order.extensions.len(), 2);
+        assert!(!order.extension_requested);
+        // No bill of lading yet, so the order goes back to awaiting shipment
+        assert!(order.state == OrderState::PendingShipment);
+    }
+
+    #[test]
+    fn set_terms_bounds_extension_response_window() {
+        let config = bounded_config();
+        let mut order = blank_order();
+        let mut terms = valid_terms();
+        order.set_terms(&terms, 0, &config).unwrap();
+        assert_eq!(order.extension_response_window, SECONDS_PER_DAY);
+        assert_eq!(order.extension_response_deadline, 0);
+
+        let err: Error = EscrowError::InvalidExtensionPolicy.into();
+        terms.extension_response_window = 0;
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+        terms.extension_response_window = config.max_deadline + 1;
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+    }
+
+    #[test]
+    fn clear_extension_request_resets_response_deadline() {
+        let mut order = blank_order();
+        order.extension_requested = true;
+        order.extension_response_deadline = 5_000;
+        order.bill_of_lading_hash = [1u8; 32];
+        order.clear_extension_request();
+        assert_eq!(order.extension_response_deadline, 0);
+        // Shipped goods return the order to in transit
+        assert!(order.state == OrderState::InTransit);
+    }
+
+    fn panel_order(size: usize, threshold: u8) -> (Order, Vec<Pubkey>) {
+        let panel: Vec<Pubkey> = (0..size).map(|_| Pubkey::new_unique()).collect();
+        let mut terms = valid_terms();
+        terms.verifier_panel = panel.clone();
+        terms.verifier_threshold = threshold;
+        let mut order = blank_order();
+        order.set_terms(&terms, 0, &bounded_config()).unwrap();
+        (order, panel)
+    }
+
+    #[test]
+    fn single_verifier_approves_alone() {
+        let mut order = blank_order();
+        order.verifier = Pubkey::new_unique();
+        let verifier = order.verifier;
+        assert!(order.verifier_approves(&verifier, &VerifierVote::ConfirmDelivery));
+        assert!(!order.verifier_approves(&Pubkey::new_unique(), &VerifierVote::ConfirmDelivery));
+    }
+
+    #[test]
+    fn panel_needs_threshold_of_matching_votes() {
+        let (mut order, panel) = panel_order(3, 2);
+        let vote = VerifierVote::ResolveDispute { exporter_bps: 6_000 };
+        order.verifier_votes[0] = Some(vote.clone());
+        assert_eq!(order.panel_votes_for(&vote), 1);
+        assert!(!order.verifier_approves(&panel[0], &vote));
+
+        // A different split does not count towards the quorum
+        order.verifier_votes[1] = Some(VerifierVote::ResolveDispute { exporter_bps: 5_000 });
+        assert!(!order.verifier_approves(&panel[0], &vote));
+
+        order.verifier_votes[2] = Some(vote.clone());
+        assert_eq!(order.panel_votes_for(&vote), 2);
+        assert!(order.verifier_approves(&panel[1], &vote));
+        // Outsiders, including the order's single verifier key, cannot execute
+        assert!(!order.verifier_approves(&order.verifier.clone(), &vote));
+        assert!(!order.verifier_approves(&Pubkey::new_unique(), &vote));
+    }
+
+    #[test]
+    fn set_terms_validates_panel() {
+        let config = bounded_config();
+        let mut order = blank_order();
+        let err: Error = EscrowError::InvalidVerifierPanel.into();
+        let member = Pubkey::new_unique();
+        let mut terms = valid_terms();
+
+        terms.verifier_threshold = 1;
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+        terms.verifier_panel = vec![member, Pubkey::new_unique()];
+        terms.verifier_threshold = 3;
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+        terms.verifier_threshold = 0;
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+        terms.verifier_panel = vec![member, member];
+        terms.verifier_threshold = 1;
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+        terms.verifier_panel = (0..=MAX_VERIFIERS).map(|_| Pubkey::new_unique()).collect();
+        assert_eq!(order.set_terms(&terms, 0, &config).unwrap_err(), err);
+
+        let (order, _) = panel_order(MAX_VERIFIERS, MAX_VERIFIERS as u8);
+        assert_eq!(order.verifier_votes.len(), MAX_VERIFIERS);
+        assert!(order.verifier_votes.iter().all(|v| v.is_none()));
+    }
+
+    fn registered_verifier(authority: Pubkey, stake: u64) -> Verifier {
+        let (_, bump) = Pubkey::find_program_address(&[b"verifier", authority.as_ref()], &crate::ID);
+        Verifier {
+            authority,
+            name_hash: [0u8; 32],
+            jurisdictions: Vec::new(),
+            fee_bps: 0,
+            flat_fee: 0,
+            active: true,
+            suspended: false,
+            stake,
+            unbonding_amount: 0,
+            unbonding_until: 0,
+            bump,
+        }
+    }
+
+    #[test]
+    fn verifier_profile_is_validated() {
+        let mut verifier = registered_verifier(Pubkey::new_unique(), 0);
+        let profile = VerifierProfile {
+            name_hash: [7u8; 32],
+            jurisdictions: vec![*b"DE"; MAX_JURISDICTIONS],
+            fee_bps: 100,
+            flat_fee: 5,
+            active: false,
+        };
+        verifier.set_profile(profile.clone()).unwrap();
+        assert!(!verifier.active);
+
+        let err: Error = EscrowError::InvalidVerifierProfile.into();
+        let mut too_many = profile.clone();
+        too_many.jurisdictions.push(*b"FR");
+        assert_eq!(verifier.set_profile(too_many).unwrap_err(), err);
+        let mut fee = profile;
+        fee.fee_bps = BPS_DENOMINATOR as u16 + 1;
+        assert_eq!(verifier.set_profile(fee).unwrap_err(), err);
+    }
+
+    #[test]
+    fn eligibility_needs_active_unsuspended_and_stake() {
+        let mut config = blank_config();
+        config.min_verifier_stake = 100;
+        let mut verifier = registered_verifier(Pubkey::new_unique(), 100);
+        verifier.check_eligible(&config).unwrap();
+
+        verifier.stake = 99;
+        assert_eq!(verifier.check_eligible(&config).unwrap_err(), EscrowError::InsufficientStake.into());
+        verifier.stake = 100;
+        verifier.suspended = true;
+        assert_eq!(verifier.check_eligible(&config).unwrap_err(), EscrowError::VerifierNotActive.into());
+        verifier.suspended = false;
+        verifier.active = false;
+        assert_eq!(verifier.check_eligible(&config).unwrap_err(), EscrowError::VerifierNotActive.into());
+    }
+
+    #[test]
+    fn check_panel_requires_registered_members_in_order() {
+        let mut config = blank_config();
+        config.min_verifier_stake = 100;
+        let member = Pubkey::new_unique();
+        let entry = registered_verifier(member, 100);
+        let key = Pubkey::create_program_address(&[b"verifier", member.as_ref(), &[entry.bump]], &crate::ID).unwrap();
+        let mut data = Vec::new();
+        entry.try_serialize(&mut data).unwrap();
+        let mut lamports = 0;
+        let owner = crate::ID;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        let hook_key = Pubkey::new_unique();
+        let mut hook_lamports = 0;
+        let mut hook_data = Vec::new();
+        let hook = AccountInfo::new(&hook_key, false, false, &mut hook_lamports, &mut hook_data, &owner, false, 0);
+        let accounts = [info, hook];
+
+        // Without the policy the panel is not checked and every account is left for hooks
+        assert_eq!(Verifier::check_panel(&[member], &accounts, &config).unwrap().len(), 2);
+
+        config.require_registered_verifier = true;
+        let rest = Verifier::check_panel(&[member], &accounts, &config).unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].key(), hook_key);
+
+        let err: Error = EscrowError::VerifierNotRegistered.into();
+        assert_eq!(Verifier::check_panel(&[member, Pubkey::new_unique()], &accounts[..1], &config).unwrap_err(), err);
+        // An entry for someone else does not vouch for this member
+        assert_eq!(Verifier::check_panel(&[Pubkey::new_unique()], &accounts, &config).unwrap_err(), err);
+    }
+
+    #[test]
+    fn native_mint_covers_both_token_programs() {
+        assert!(is_native_mint(&spl_token::native_mint::ID));
+        assert!(is_native_mint(&spl_token_2022::native_mint::ID));
+        assert!(!is_native_mint(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn vault_constructors_use_the_order_seeds() {
+        let mut order = blank_order();
+        order.escrow_bump = 7;
+        order.bond_bump = 9;
+        order.token_mint = Some(spl_token::native_mint::ID);
+        let order_key = Pubkey::new_unique();
+        let owner = crate::ID;
+        let mut order_lamports = 0;
+        let mut order_data = Vec::new();
+        order.try_serialize(&mut order_data).unwrap();
+        let order_info = AccountInfo::new(&order_key, false, true, &mut order_lamports, &mut order_data, &owner, false, 0);
+        let order_account = Account::<Order>::try_from(&order_info).unwrap();
+
+        let pda_key = Pubkey::new_unique();
+        let mut pda_lamports = 0;
+        let mut pda_data = Vec::new();
+        let pda = AccountInfo::new(&pda_key, false, true, &mut pda_lamports, &mut pda_data, &owner, false, 0);
+        let system_key = System::id();
+        let mut system_lamports = 0;
+        let mut system_data = Vec::new();
+        let system_info = AccountInfo::new(&system_key, false, false, &mut system_lamports, &mut system_data, &system_key, true, 0);
+        let system_program = Program::<System>::try_from(&system_info).unwrap();
+
+        let escrow = EscrowVault::escrow(&order_account, &pda, None, None, None, &[], &system_program);
+        assert_eq!(escrow.seed, b"escrow_pda");
+        assert_eq!(escrow.order_key, order_key);
+        assert_eq!(escrow.bump, 7);
+        assert_eq!(escrow.token_mint, order.token_mint);
+        let bond = EscrowVault::bond(&order_account, &pda, None, None, None, &[], &system_program);
+        assert_eq!(bond.seed, b"bond_pda");
+        assert_eq!(bond.bump, 9);
+        assert_eq!(bond.authority.key(), pda_key);
+    }
+
+    // Serves CPIs by running the token programs in-process, so vault deposits, payouts and
+    // closes can be exercised without a validator. PDA signers are taken on trust from the
+    // seeds, and sysvars read as their defaults.
+    struct TokenProgramStub;
+
+    impl anchor_lang::solana_program::program_stubs::SyscallStubs for TokenProgramStub {
+        fn sol_invoke_signed(
+            &self,
+            instruction: &anchor_lang::solana_program::instruction::Instruction,
+            account_infos: &[AccountInfo],
+            _signers_seeds: &[&[&[u8]]],
+        ) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+            let accounts: Vec<AccountInfo> = instruction
+                .accounts
+                .iter()
+                .map(|meta| {
+                    let mut info = account_infos.iter().find(|info| info.key == &meta.pubkey).unwrap().clone();
+                    info.is_signer |= meta.is_signer;
+                    info
+                })
+                .collect();
+            if instruction.program_id == spl_token_2022::ID {
+                spl_token_2022::processor::Processor::process(&instruction.program_id, &accounts, &instruction.data)
+            } else {
+                spl_token::processor::Processor::process(&instruction.program_id, &accounts, &instruction.data)
+            }
+        }
+
+        fn sol_get_clock_sysvar(&self, _var_addr: *mut u8) -> u64 {
+            anchor_lang::solana_program::entrypoint::SUCCESS
+        }
+
+        fn sol_get_rent_sysvar(&self, _var_addr: *mut u8) -> u64 {
+            anchor_lang::solana_program::entrypoint::SUCCESS
+        }
+    }
+
+    fn install_token_program_stub() {
+        static INSTALL: std::sync::Once = std::sync::Once::new();
+        INSTALL.call_once(|| {
+            anchor_lang::solana_program::program_stubs::set_syscall_stubs(Box::new(TokenProgramStub));
+        });
+    }
+
+    fn spl_mint_data(decimals: u8) -> Vec<u8> {
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        let mint = spl_token::state::Mint { decimals, is_initialized: true, ..Default::default() };
+        spl_token::state::Mint::pack(mint, &mut data).unwrap();
+        data
+    }
+
+    fn spl_token_account_data(mint: Pubkey, owner: Pubkey, amount: u64) -> Vec<u8> {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        let account = spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        spl_token::state::Account::pack(account, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn close_vault_sweeps_a_donated_balance() {
+        install_token_program_stub();
+        // Copies rather than `&spl_token::ID` so the token program can reassign the closed vault
+        let token_owner = spl_token::ID;
+        let order_key = Pubkey::new_unique();
+        let (pda_key, bump) = Pubkey::find_program_address(&[b"bond_pda", order_key.as_ref()], &crate::ID);
+        let mint_key = Pubkey::new_unique();
+
+        let mut pda_lamports = 0;
+        let mut pda_data = Vec::new();
+        let pda = AccountInfo::new(&pda_key, false, false, &mut pda_lamports, &mut pda_data, &crate::ID, false, 0);
+        let mut mint_lamports = 1_000_000;
+        let mut mint_data = spl_mint_data(6);
+        let mint_info = AccountInfo::new(&mint_key, false, false, &mut mint_lamports, &mut mint_data, &token_owner, false, 0);
+        let mint = InterfaceAccount::<Mint>::try_from(&mint_info).unwrap();
+        // Bond already paid out; 25 tokens were sent to the vault by someone else
+        let vault_key = Pubkey::new_unique();
+        let mut vault_lamports = 2_039_280;
+        let mut vault_data = spl_token_account_data(mint_key, pda_key, 25);
+        let vault_info = AccountInfo::new(&vault_key, false, true, &mut vault_lamports, &mut vault_data, &token_owner, false, 0);
+        let vault = InterfaceAccount::<TokenAccount>::try_from(&vault_info).unwrap();
+        let exporter_key = Pubkey::new_unique();
+        let mut exporter_lamports = 0;
+        let mut exporter_data = Vec::new();
+        let system_key = System::id();
+        let exporter = AccountInfo::new(&exporter_key, true, true, &mut exporter_lamports, &mut exporter_data, &system_key, false, 0);
+        let exporter_token_key = Pubkey::new_unique();
+        let mut exporter_token_lamports = 2_039_280;
+        let mut exporter_token_data = spl_token_account_data(mint_key, exporter_key, 0);
+        let exporter_token_info = AccountInfo::new(&exporter_token_key, false, true, &mut exporter_token_lamports, &mut exporter_token_data, &token_owner, false, 0);
+        let exporter_token = InterfaceAccount::<TokenAccount>::try_from(&exporter_token_info).unwrap();
+        let mut program_lamports = 0;
+        let mut program_data = Vec::new();
+        let program_key = spl_token::ID;
+        let program_info = AccountInfo::new(&program_key, false, false, &mut program_lamports, &mut program_data, &token_owner, true, 0);
+        let token_program = Interface::<TokenInterface>::try_from(&program_info).unwrap();
+        let mut system_lamports = 0;
+        let mut system_data = Vec::new();
+        let system_info = AccountInfo::new(&system_key, false, false, &mut system_lamports, &mut system_data, &system_key, true, 0);
+        let system_program = Program::<System>::try_from(&system_info).unwrap();
+
+        let vault_ref = EscrowVault {
+            seed: b"bond_pda",
+            order_key,
+            bump,
+            token_mint: Some(mint_key),
+            authority: &pda,
+            token_vault: Some(&vault),
+            token_program: Some(&token_program),
+            mint: Some(&mint),
+            remaining_accounts: &[],
+            system_program: &system_program,
+        };
+        // Without somewhere to send the donation the vault can't be closed
+        assert_eq!(
+            vault_ref.close_vault(&exporter, None).unwrap_err(),
+            EscrowError::MissingSPLAccount.into()
+        );
+        vault_ref.close_vault(&exporter, Some(&exporter_token)).unwrap();
+        assert_eq!(token_balance(&exporter_token).unwrap(), 25);
+        assert_eq!(vault_info.lamports(), 0);
+        assert_eq!(exporter.lamports(), 2_039_280);
+    }
+
+    #[test]
+    fn cancel_needs_the_exporter_once_the_deadline_is_agreed() {
+        let mut order = blank_order();
+        order.state = OrderState::PendingDeadlineApproval;
+        assert!(order.check_cancellable(false, 0).is_ok());
+        order.state = OrderState::PendingShipment;
+        assert_eq!(order.check_cancellable(false, 0).unwrap_err(), EscrowError::Unauthorized.into());
+        assert!(order.check_cancellable(true, 0).is_ok());
+        order.state = OrderState::InTransit;
+        assert_eq!(order.check_cancellable(true, 0).unwrap_err(), EscrowError::InvalidState.into());
+    }
+
+    #[test]
+    fn cancel_waits_out_the_acceptance_window() {
+        let mut order = blank_order();
+        order.state = OrderState::PendingAcceptance;
+        order.acceptance_deadline = 1_000;
+        assert_eq!(
+            order.check_cancellable(false, 1_000).unwrap_err(),
+            EscrowError::AcceptanceWindowOpen.into()
+        );
+        assert!(order.check_cancellable(false, 1_001).is_ok());
+        assert!(order.check_cancellable(true, 1_000).is_ok());
+    }
+
+    #[test]
+    fn close_needs_a_final_state_and_settled_funds() {
+        let mut order = blank_order();
+        order.state = OrderState::Delivered;
+        assert_eq!(order.check_closable().unwrap_err(), EscrowError::InvalidState.into());
+        order.state = OrderState::Completed;
+        order.amount = 1_000;
+        order.released_amount = 600;
+        order.refunded_amount = 300;
+        assert_eq!(order.check_closable().unwrap_err(), EscrowError::FundsNotSettled.into());
+        order.refunded_amount = 400;
+        order.bond_posted = true;
+        assert_eq!(order.check_closable().unwrap_err(), EscrowError::FundsNotSettled.into());
+        order.bond_posted = false;
+        assert!(order.check_closable().is_ok());
+    }
+
+    #[test]
+    fn simulated_time_must_be_within_the_skew() {
+        let mut config = blank_config();
+        assert_eq!(config.resolve_time_at(None, 1_000).unwrap(), 1_000);
+        // No skew configured: only the cluster clock is accepted
+        assert_eq!(config.resolve_time_at(Some(1_000), 1_000).unwrap_err(), EscrowError::ClockSkewExceeded.into());
+        config.max_clock_skew = 60;
+        assert_eq!(config.resolve_time_at(Some(940), 1_000).unwrap(), 940);
+        assert_eq!(config.resolve_time_at(Some(1_060), 1_000).unwrap(), 1_060);
+        assert_eq!(config.resolve_time_at(Some(1_061), 1_000).unwrap_err(), EscrowError::ClockSkewExceeded.into());
+        // Extreme values are rejected rather than overflowing
+        assert_eq!(config.resolve_time_at(Some(i64::MIN), 1_000).unwrap_err(), EscrowError::ClockSkewExceeded.into());
+        assert_eq!(config.resolve_time_at(Some(i64::MAX), -1_000).unwrap_err(), EscrowError::ClockSkewExceeded.into());
+    }
+
+    #[test]
+    fn dispute_split_covers_the_remaining_escrow() {
+        let mut order = blank_order();
+        order.amount = 1_001;
+        order.released_amount = 200;
+        order.refunded_amount = 100;
+        assert_eq!(order.dispute_split(0), (0, 701));
+        assert_eq!(order.dispute_split(10_000), (701, 0));
+        // 701 * 33.33% = 233.6; the importer keeps the remainder
+        assert_eq!(order.dispute_split(3_333), (233, 468));
+        assert_eq!(order.dispute_split(5_000), (350, 351));
+    }
+
+    #[test]
+    fn truncation_keeps_whole_characters() {
+        assert_eq!(truncate_at_char_boundary("short", 80), "short");
+        assert_eq!(truncate_at_char_boundary("abcdef", 3), "abc");
+        // "é" is two bytes, so a cut through it backs off to the previous character
+        assert_eq!(truncate_at_char_boundary("aéb", 2), "a");
+        assert_eq!(truncate_at_char_boundary(&"€".repeat(40), 80), "€".repeat(26));
+        let mut order = blank_order();
+        order.add_history_entry(OrderState::Disputed, "ü".repeat(60), 0);
+        assert_eq!(order.history[0].description, "ü".repeat(50));
+    }
+
+    #[test]
+    fn allowed_mint_limits_are_inclusive() {
+        let mut allowed = AllowedMint { mint: Pubkey::new_unique(), enabled: true, min_amount: 100, max_amount: 1_000, min_fee: 0, bump: 0 };
+        assert_eq!(allowed.check_amount(99).unwrap_err(), EscrowError::OrderAmountOutOfRange.into());
+        assert!(allowed.check_amount(100).is_ok());
+        assert!(allowed.check_amount(1_000).is_ok());
+        assert_eq!(allowed.check_amount(1_001).unwrap_err(), EscrowError::OrderAmountOutOfRange.into());
+        // No upper limit
+        allowed.max_amount = 0;
+        assert!(allowed.check_amount(u64::MAX).is_ok());
+    }
+
+    fn valid_params() -> ConfigParams {
+        ConfigParams {
+            treasury: Pubkey::new_unique(),
+            min_deadline: 3_600,
+            max_deadline: 30 * SECONDS_PER_DAY,
+            fee_bps: 50,
+            min_fee: 10,
+            max_clock_skew: 0,
+            paused: false,
+            require_registered_verifier: false,
+            min_verifier_stake: 0,
+            verifier_unbonding_period: 0,
+        }
+    }
+
+    #[test]
+    fn config_apply_validates_params() {
+        let mut config = blank_config();
+        let err = EscrowError::InvalidConfig.into();
+        let mut params = valid_params();
+        params.min_deadline = 0;
+        assert_eq!(config.apply(params).unwrap_err(), err);
+        let mut params = valid_params();
+        params.min_deadline = params.max_deadline + 1;
+        assert_eq!(config.apply(params).unwrap_err(), err);
+        let mut params = valid_params();
+        params.fee_bps = 10_001;
+        assert_eq!(config.apply(params).unwrap_err(), err);
+        let mut params = valid_params();
+        params.max_clock_skew = -1;
+        assert_eq!(config.apply(params).unwrap_err(), err);
+        let mut params = valid_params();
+        params.verifier_unbonding_period = -1;
+        assert_eq!(config.apply(params).unwrap_err(), err);
+
+        // The bounds themselves are accepted
+        let mut params = valid_params();
+        params.min_deadline = params.max_deadline;
+        params.fee_bps = 10_000;
+        config.apply(params.clone()).unwrap();
+        assert_eq!(config.min_deadline, params.max_deadline);
+        assert_eq!(config.fee_bps, 10_000);
+        assert_eq!(config.treasury, params.treasury);
+    }
+
+    #[test]
+    fn config_event_mirrors_the_settings() {
+        let mut config = blank_config();
+        config.admin = Pubkey::new_unique();
+        config.apply(valid_params()).unwrap();
+        let event = config.updated_event();
+        assert_eq!(event.version, EVENT_VERSION);
+        assert_eq!(event.admin, config.admin);
+        assert_eq!(event.treasury, config.treasury);
+        assert_eq!(event.max_deadline, config.max_deadline);
+        assert_eq!(event.min_fee, config.min_fee);
+    }
+
+    #[test]
+    fn minimum_fee_follows_the_order_not_the_config() {
+        let mut config = blank_config();
+        config.fee_bps = 10;
+        config.min_fee = 5_000; // Lamports, for SOL orders
+        let mut order = blank_order();
+        // A 6-decimal stablecoin order with a per-mint minimum of 0.01
+        order.min_fee = 10_000;
+        assert_eq!(config.protocol_fee(1_000_000, order.min_fee), 10_000);
+        assert_eq!(config.protocol_fee(100_000_000, order.min_fee), 100_000);
+    }
+
+    fn token_2022_mint_data(decimals: u8, fee_bps: u16) -> Vec<u8> {
+        use spl_token_2022::extension::transfer_fee::{TransferFee, TransferFeeConfig};
+        use spl_token_2022::extension::{BaseStateWithExtensionsMut, ExtensionType, StateWithExtensionsMut};
+        let len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[ExtensionType::TransferFeeConfig]).unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data).unwrap();
+        let fee = TransferFee {
+            epoch: 0.into(),
+            maximum_fee: u64::MAX.into(),
+            transfer_fee_basis_points: fee_bps.into(),
+        };
+        let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+        config.older_transfer_fee = fee;
+        config.newer_transfer_fee = fee;
+        state.base = spl_token_2022::state::Mint { decimals, is_initialized: true, ..Default::default() };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        data
+    }
+
+    fn token_2022_account_data(mint: Pubkey, owner: Pubkey, amount: u64) -> Vec<u8> {
+        use spl_token_2022::extension::{BaseStateWithExtensionsMut, ExtensionType, StateWithExtensionsMut};
+        let len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&[ExtensionType::TransferFeeAmount]).unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack_uninitialized(&mut data).unwrap();
+        state.init_extension::<TransferFeeAmount>(true).unwrap();
+        state.base = spl_token_2022::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token_2022::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        data
+    }
+
+    #[test]
+    fn deposit_records_what_the_vault_received_after_transfer_fees() {
+        install_token_program_stub();
+        let token_owner = spl_token_2022::ID;
+        let order_key = Pubkey::new_unique();
+        let (pda_key, bump) = Pubkey::find_program_address(&[b"escrow_pda", order_key.as_ref()], &crate::ID);
+        let mint_key = Pubkey::new_unique();
+
+        let mut pda_lamports = 0;
+        let mut pda_data = Vec::new();
+        let pda = AccountInfo::new(&pda_key, false, false, &mut pda_lamports, &mut pda_data, &crate::ID, false, 0);
+        // 1% transfer fee
+        let mut mint_lamports = 1_000_000;
+        let mut mint_data = token_2022_mint_data(6, 100);
+        let mint_info = AccountInfo::new(&mint_key, false, false, &mut mint_lamports, &mut mint_data, &token_owner, false, 0);
+        let mint = InterfaceAccount::<Mint>::try_from(&mint_info).unwrap();
+        let vault_key = Pubkey::new_unique();
+        let mut vault_lamports = 2_039_280;
+        let mut vault_data = token_2022_account_data(mint_key, pda_key, 0);
+        let vault_info = AccountInfo::new(&vault_key, false, true, &mut vault_lamports, &mut vault_data, &token_owner, false, 0);
+        let vault = InterfaceAccount::<TokenAccount>::try_from(&vault_info).unwrap();
+        let system_key = System::id();
+        let importer_key = Pubkey::new_unique();
+        let mut importer_lamports = 0;
+        let mut importer_data = Vec::new();
+        let importer = AccountInfo::new(&importer_key, true, true, &mut importer_lamports, &mut importer_data, &system_key, false, 0);
+        let importer_token_key = Pubkey::new_unique();
+        let mut importer_token_lamports = 2_039_280;
+        let mut importer_token_data = token_2022_account_data(mint_key, importer_key, 5_000);
+        let importer_token_info = AccountInfo::new(&importer_token_key, false, true, &mut importer_token_lamports, &mut importer_token_data, &token_owner, false, 0);
+        let importer_token = InterfaceAccount::<TokenAccount>::try_from(&importer_token_info).unwrap();
+        let mut program_lamports = 0;
+        let mut program_data = Vec::new();
+        let program_key = spl_token_2022::ID;
+        let program_info = AccountInfo::new(&program_key, false, false, &mut program_lamports, &mut program_data, &token_owner, true, 0);
+        let token_program = Interface::<TokenInterface>::try_from(&program_info).unwrap();
+        let mut system_lamports = 0;
+        let mut system_data = Vec::new();
+        let system_info = AccountInfo::new(&system_key, false, false, &mut system_lamports, &mut system_data, &system_key, true, 0);
+        let system_program = Program::<System>::try_from(&system_info).unwrap();
+
+        let escrow = EscrowVault {
+            seed: b"escrow_pda",
+            order_key,
+            bump,
+            token_mint: Some(mint_key),
+            authority: &pda,
+            token_vault: Some(&vault),
+            token_program: Some(&token_program),
+            mint: Some(&mint),
+            remaining_accounts: &[],
+            system_program: &system_program,
+        };
+        assert_eq!(escrow.deposit(&importer, Some(&importer_token), 1_000).unwrap(), 990);
+        assert_eq!(token_balance(&vault).unwrap(), 990);
+        assert_eq!(withheld_fees(&vault).unwrap(), 10);
+        assert_eq!(token_balance(&importer_token).unwrap(), 4_000);
+        assert_eq!(withheld_fees(&importer_token).unwrap(), 0);
+    }
+
+    #[test]
+    fn legacy_token_accounts_have_no_withheld_fees() {
+        let key = Pubkey::new_unique();
+        let owner = spl_token::ID;
+        let mut lamports = 0;
+        let mut data = spl_token_account_data(Pubkey::new_unique(), Pubkey::new_unique(), 42);
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        let account = InterfaceAccount::<TokenAccount>::try_from(&info).unwrap();
+        assert_eq!(token_balance(&account).unwrap(), 42);
+        assert_eq!(withheld_fees(&account).unwrap(), 0);
+    }
+
+    #[test]
+    fn sol_vault_pays_out_without_touching_its_rent_reserve() {
+        install_token_program_stub();
+        let order_key = Pubkey::new_unique();
+        let owner = crate::ID;
+        let reserve = Rent::default().minimum_balance(SolVault::LEN);
+        let pda_key = Pubkey::new_unique();
+        let mut pda_lamports = reserve + 1_000;
+        let mut pda_data = vec![0; SolVault::LEN];
+        let pda = AccountInfo::new(&pda_key, false, true, &mut pda_lamports, &mut pda_data, &owner, false, 0);
+        let system_key = System::id();
+        let recipient_key = Pubkey::new_unique();
+        let mut recipient_lamports = 0;
+        let mut recipient_data = Vec::new();
+        let recipient = AccountInfo::new(&recipient_key, false, true, &mut recipient_lamports, &mut recipient_data, &system_key, false, 0);
+        let mut system_lamports = 0;
+        let mut system_data = Vec::new();
+        let system_info = AccountInfo::new(&system_key, false, false, &mut system_lamports, &mut system_data, &system_key, true, 0);
+        let system_program = Program::<System>::try_from(&system_info).unwrap();
+
+        let escrow = EscrowVault {
+            seed: b"escrow_pda",
+            order_key,
+            bump: 0,
+            token_mint: None,
+            authority: &pda,
+            token_vault: None,
+            token_program: None,
+            mint: None,
+            remaining_accounts: &[],
+            system_program: &system_program,
+        };
+        assert_eq!(escrow.pay(&recipient, None, 1_001).unwrap_err(), EscrowError::InsufficientVaultBalance.into());
+        escrow.pay(&recipient, None, 600).unwrap();
+        escrow.pay(&recipient, None, 400).unwrap();
+        assert_eq!(pda.lamports(), reserve);
+        assert_eq!(recipient.lamports(), 1_000);
+        assert_eq!(escrow.pay(&recipient, None, 1).unwrap_err(), EscrowError::InsufficientVaultBalance.into());
+    }
+
+    #[test]
+    fn amendment_stays_above_what_has_been_paid_out() {
+        let mut order = blank_order();
+        order.state = OrderState::PendingShipment;
+        order.amount = 1_000;
+        order.released_amount = 200;
+        order.refunded_amount = 100;
+        let err = EscrowError::InvalidPartialAmount.into();
+        assert_eq!(order.check_amendment(300).unwrap_err(), err);
+        assert_eq!(order.check_amendment(1_000).unwrap_err(), err);
+        assert!(order.check_amendment(301).is_ok());
+        assert!(order.check_amendment(5_000).is_ok());
+    }
+
+    #[test]
+    fn amendment_is_only_for_unshipped_plain_orders() {
+        let mut order = blank_order();
+        order.amount = 1_000;
+        order.state = OrderState::InTransit;
+        assert_eq!(order.check_amendment(2_000).unwrap_err(), EscrowError::InvalidState.into());
+        order.state = OrderState::PendingDeadlineApproval;
+        assert_eq!(order.check_amendment(2_000).unwrap_err(), EscrowError::InvalidState.into());
+        order.state = OrderState::PendingShipment;
+        order.set_milestones(vec![milestone(1_000, 10_000)], 0).unwrap();
+        assert_eq!(order.check_amendment(2_000).unwrap_err(), EscrowError::MilestoneScheduleActive.into());
+    }
 }